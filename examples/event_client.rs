@@ -0,0 +1,189 @@
+//! Checks the HTTP event stream against a running queue, without a browser.
+//!
+//! Subscribes to a course's events, makes a few changes through the API and
+//! checks the stream reports each of them, in order. Needs a staff token
+//! for the course, from the "token" command or `POST /api/login`.
+//!
+//! ```text
+//! QUEUE_TOKEN=<token> cargo run --example event_client -- [address] [course] [netid]
+//! ```
+//!
+//! Everything it does stays in the course's history: the student's join
+//! and leave are a visit in their record, and checking in and out is a
+//! shift in the staff reports and payroll. So it only runs against a course
+//! kept for testing, whose id starts with "test", e.g. a `[[course]]` with
+//! `id = "test53"` in queue.toml, and only while nobody is in its line.
+//! `netid` has to be on that course's roster and the course can't ask
+//! students to verify who they are. Exits with an error if an event is
+//! missing or out of order.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    process::exit,
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+
+/// How long to wait for each event before giving up.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sends a request and returns the status code and body.
+fn request(address: &str, method: &str, path: &str, token: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(address).unwrap_or_else(|err| {
+        eprintln!("Failed to connect to {}: {}", address, err);
+        exit(1);
+    });
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\n\
+         Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        method,
+        path,
+        address,
+        token,
+        body.len(),
+        body
+    )
+    .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .unwrap_or(0);
+    let body = response
+        .split_once("\r\n\r\n")
+        .map(|(_, body)| body.to_owned())
+        .unwrap_or_default();
+    (status, body)
+}
+
+/// Opens the event stream. Event names arrive on the receiver.
+fn subscribe(address: &str, course: &str, token: &str) -> Receiver<String> {
+    let mut stream = TcpStream::connect(address).unwrap_or_else(|err| {
+        eprintln!("Failed to connect to {}: {}", address, err);
+        exit(1);
+    });
+    write!(
+        stream,
+        "GET /api/courses/{}/events HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\n\r\n",
+        course, address, token
+    )
+    .unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            if let Some(event) = line.strip_prefix("event: ") {
+                if sender.send(event.to_owned()).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    receiver
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:8053".to_owned());
+    let course = args.next().unwrap_or_else(|| "test53".to_owned());
+    let net_id = args.next().unwrap_or_else(|| "testclient".to_owned());
+    let Ok(token) = std::env::var("QUEUE_TOKEN") else {
+        eprintln!("Set QUEUE_TOKEN to a staff token for {}.", course);
+        exit(1);
+    };
+
+    let (status, queue) = request(
+        &address,
+        "GET",
+        &format!("/api/courses/{}/queue", course),
+        &token,
+        "",
+    );
+    if status != 200 {
+        eprintln!("Reading the queue failed with {}: {}", status, queue);
+        exit(1);
+    }
+    // By the id in the answer, the argument may be an alias.
+    if !queue.contains("\"course\":\"test") {
+        eprintln!(
+            "{} isn't a test course. Everything this does stays in the course's reports, \
+             add a course whose id starts with \"test\" for it.",
+            course
+        );
+        exit(1);
+    }
+    if !queue.contains("\"queue\":[]") {
+        eprintln!(
+            "The queue of {} isn't empty, run this when nobody is in line.",
+            course
+        );
+        exit(1);
+    }
+
+    let post = |path: &str, body: &str| request(&address, "POST", path, &token, body);
+
+    let events = subscribe(&address, &course, &token);
+    if let Err(err) = expect(&events, "snapshot") {
+        eprintln!("{}", err);
+        exit(1);
+    }
+
+    // Checking in fails if already checked in, then there is no event.
+    let (status, _) = post(&format!("/api/courses/{}/checkin", course), "");
+    let checked_in = status == 200;
+    if checked_in {
+        if let Err(err) = expect(&events, "checked_in") {
+            eprintln!("{}", err);
+            post(&format!("/api/courses/{}/checkout", course), "");
+            exit(1);
+        }
+    }
+
+    let student = format!("{{\"net_id\": \"{}\"}}", net_id);
+    let mut steps = vec![
+        ("join", student.as_str(), "added"),
+        ("lock", "", "locked"),
+        ("unlock", "", "unlocked"),
+        ("leave", student.as_str(), "left"),
+    ];
+    if checked_in {
+        steps.push(("checkout", "", "checked_out"));
+    }
+    for (action, body, event) in steps {
+        let (status, response) = post(&format!("/api/courses/{}/{}", course, action), body);
+        let result = match status {
+            200 => expect(&events, event),
+            _ => Err(format!("{} failed with {}: {}", action, status, response)),
+        };
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            // Take the student out of line and check out again.
+            post(&format!("/api/courses/{}/leave", course), &student);
+            if checked_in {
+                post(&format!("/api/courses/{}/checkout", course), "");
+            }
+            exit(1);
+        }
+    }
+
+    println!("OK, every event arrived in order.");
+}
+
+fn expect(events: &Receiver<String>, expected: &str) -> Result<(), String> {
+    match events.recv_timeout(TIMEOUT) {
+        Ok(event) if event == expected => {
+            println!("Got {}.", event);
+            Ok(())
+        }
+        Ok(event) => Err(format!("Expected {}, got {}.", expected, event)),
+        Err(_) => Err(format!("Expected {}, got nothing.", expected)),
+    }
+}
@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Local, Utc};
+
+use crate::{
+    format_duration, journal::Event, Call, CommandResult, Outcome, QueueState, QueuedStudent,
+};
+
+impl QueueState {
+    /// The student `staff` called and is waiting for, if any.
+    pub fn called_by(&self, staff: &str) -> Option<&QueuedStudent> {
+        self.queue.iter().find(|queued| {
+            queued
+                .called
+                .as_ref()
+                .is_some_and(|call| call.staff == staff)
+        })
+    }
+
+    /// How long a called student has left to confirm, as of `now`.
+    pub fn grace_left(&self, call: &Call, now: DateTime<Utc>) -> Duration {
+        let deadline = call.at + Duration::from_secs(self.config.grace_minutes * 60);
+        (deadline - now).to_std().unwrap_or_default()
+    }
+
+    /// Calls the next student. They have to confirm they are there with
+    /// `here` before the grace period runs out, or they move back.
+    ///
+    /// `call`
+    pub fn call(&mut self, staff: &str) -> CommandResult {
+        self.ensure_checked_in(staff)?;
+
+        if let Some(session) = self.helping.iter().find(|session| session.staff == staff) {
+            return Err(format!(
+                "Already helping {}, use \"done\" or \"requeue\" first.",
+                session.student.net_id
+            ));
+        }
+        if let Some(queued) = self.called_by(staff) {
+            return Err(format!("Already called {}.", queued.net_id));
+        }
+
+        let net_id = self.next_up()?.net_id.clone();
+        self.record(Event::Called {
+            net_id: net_id.clone(),
+            staff: staff.to_owned(),
+        })?;
+
+        println!(
+            "Called \"{}\", they have {} to confirm.",
+            self.student_name(&net_id),
+            format_duration(Duration::from_secs(self.config.grace_minutes * 60))
+        );
+        Ok(())
+    }
+
+    /// A called student confirms they are there, and the staff member who
+    /// called them starts helping them.
+    ///
+    /// `here <netid>`
+    pub fn confirm(&mut self, net_id: &str) -> CommandResult {
+        let Some(call) = self.ensure_queued(net_id)?.called.clone() else {
+            return Err(format!("{} hasn't been called yet.", net_id));
+        };
+
+        self.record(Event::Claimed {
+            net_id: net_id.to_owned(),
+            staff: call.staff.clone(),
+        })?;
+
+        println!("{} is on their way to help you.", call.staff);
+        Ok(())
+    }
+
+    /// Moves back, or drops, every called student whose grace period ran
+    /// out by `now`.
+    pub fn expire_calls(&mut self, now: DateTime<Utc>) {
+        let expired = self
+            .queue
+            .iter()
+            .filter(|queued| {
+                queued
+                    .called
+                    .as_ref()
+                    .is_some_and(|call| self.grace_left(call, now).is_zero())
+            })
+            .map(|queued| (queued.net_id.clone(), queued.missed + 1))
+            .collect::<Vec<(String, u32)>>();
+
+        for (net_id, missed) in expired {
+            let result = if missed >= self.config.missed_call_limit {
+                self.record(Event::NoShow {
+                    net_id: net_id.clone(),
+                })
+                .map(|_| {
+                    println!(
+                        "{} missed {} calls and was dropped from the queue.",
+                        net_id, missed
+                    )
+                })
+            } else {
+                let places = self.config.missed_call_places;
+                let behind = self
+                    .ordered()
+                    .iter()
+                    .skip_while(|queued| queued.net_id != net_id)
+                    .skip(1)
+                    .take(places)
+                    .map(|queued| queued.net_id.clone())
+                    .collect();
+                self.record(Event::MissedCall {
+                    net_id: net_id.clone(),
+                    places,
+                    behind,
+                })
+                .map(|_| {
+                    println!(
+                        "{} missed their call and moved back {} places.",
+                        net_id, self.config.missed_call_places
+                    )
+                })
+            };
+            if let Err(err) = result {
+                println!("{}", err);
+            }
+        }
+    }
+
+    /// Lists students who missed calls, most missed first.
+    ///
+    /// `no_shows`
+    pub fn no_shows(&mut self) -> CommandResult {
+        let mut students = self
+            .students
+            .iter()
+            .filter(|(_, student)| !student.missed_calls.is_empty())
+            .collect::<Vec<_>>();
+        if students.is_empty() {
+            println!("Nobody missed a call.");
+            return Ok(());
+        }
+        students.sort_by(|(a_id, a), (b_id, b)| {
+            b.missed_calls
+                .len()
+                .cmp(&a.missed_calls.len())
+                .then(a_id.cmp(b_id))
+        });
+
+        for (net_id, student) in students {
+            let dropped = student
+                .queue_times
+                .iter()
+                .filter(|visit| visit.outcome == Outcome::NoShow)
+                .count();
+            let last = student.missed_calls.iter().max().unwrap();
+            println!(
+                "{} ({}): missed {} calls, dropped {} times, last on {}",
+                net_id,
+                self.student_name(net_id),
+                student.missed_calls.len(),
+                dropped,
+                last.with_timezone(&Local).format("%d/%m/%Y %H:%M")
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use std::{fmt, time::Duration};
+
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{estimate::format_estimate, journal::Event, QueueState};
+
+/// Why the queue locked by itself. Locks by staff have none.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LockReason {
+    /// The session is about to end.
+    SessionEnding { end: DateTime<Utc> },
+    /// The staff on duty can't get through everyone waiting before the
+    /// session ends.
+    Full {
+        waiting: usize,
+        on_duty: usize,
+        /// The average help session the projection used.
+        average_help: Duration,
+        end: DateTime<Utc>,
+    },
+    /// The course has a schedule and no session is open. `next` is when the
+    /// next one starts, if there is one in the coming days.
+    OutsideHours { next: Option<DateTime<Utc>> },
+}
+impl LockReason {
+    /// A word or two for where a whole sentence doesn't fit.
+    pub fn label(&self) -> &'static str {
+        match self {
+            LockReason::SessionEnding { .. } => "session ending",
+            LockReason::Full { .. } => "full",
+            LockReason::OutsideHours { .. } => "outside hours",
+        }
+    }
+}
+impl fmt::Display for LockReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockReason::SessionEnding { end } => write!(
+                f,
+                "The session ends at {}.",
+                end.with_timezone(&Local).format("%H:%M")
+            ),
+            LockReason::Full {
+                waiting,
+                on_duty,
+                average_help,
+                end,
+            } => write!(
+                f,
+                "Full: {} waiting at {} each, more than {} staff can help before {}.",
+                waiting,
+                format_estimate(*average_help),
+                on_duty,
+                end.with_timezone(&Local).format("%H:%M")
+            ),
+            LockReason::OutsideHours { next: Some(next) } => write!(
+                f,
+                "Outside office hours, the next session starts {}.",
+                next.with_timezone(&Local).format("%a %d/%m at %H:%M")
+            ),
+            LockReason::OutsideHours { next: None } => {
+                write!(f, "Outside office hours, no sessions coming up.")
+            }
+        }
+    }
+}
+
+impl QueueState {
+    /// How long the staff on duty need to help everyone waiting and finish
+    /// the students they are helping, as of `now`. `None` with nobody on
+    /// duty.
+    pub fn projected_work(&self, now: DateTime<Utc>) -> Option<Duration> {
+        let on_duty = self.on_duty_count();
+        if on_duty == 0 {
+            return None;
+        }
+        let average_help = self.average_help();
+        let helping = self
+            .helping
+            .iter()
+            .map(|session| {
+                let so_far = (now - session.claimed_at).to_std().unwrap_or_default();
+                average_help.saturating_sub(so_far)
+            })
+            .sum::<Duration>();
+        Some((average_help * self.queue.len() as u32 + helping) / on_duty as u32)
+    }
+
+    /// Locks the queue once the students waiting would take longer to help
+    /// than the session has left, as of `now`. Only once per session, so
+    /// staff who unlock it again stay in charge. Does nothing outside a
+    /// scheduled session or with nobody on duty.
+    pub fn check_capacity(&mut self, now: DateTime<Utc>) {
+        let Some(session) = &self.session else {
+            return;
+        };
+        if session.closed || session.full || self.locked {
+            return;
+        }
+        let end = session.end;
+        let Some(work) = self.projected_work(now) else {
+            return;
+        };
+        let left = (end - now).to_std().unwrap_or_default();
+        if work <= left {
+            return;
+        }
+
+        let reason = LockReason::Full {
+            waiting: self.queue.len(),
+            on_duty: self.on_duty_count(),
+            average_help: self.average_help(),
+            end,
+        };
+        match self.record(Event::Full {
+            reason: reason.clone(),
+        }) {
+            Ok(()) => println!("{} locked. {}", self.config.id, reason),
+            Err(err) => println!("{}", err),
+        }
+    }
+}
@@ -0,0 +1,671 @@
+use std::{process::exit, sync::Mutex};
+
+use crate::{
+    confirm,
+    credentials::{self, prompt_new_password},
+    dashboard,
+    topics::prompt_question,
+    verify::verify_at_terminal,
+    CommandResult, Queue, QueueState,
+};
+
+/// Who may run a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Privilege {
+    /// Anyone standing at the terminal.
+    Public,
+    /// Requires a staff member to log in. For course commands they have to
+    /// be staff of that course.
+    Staff,
+}
+
+/// Who is running a course command.
+pub struct Caller {
+    /// The staff member who logged in. `None` for public commands.
+    pub staff: Option<String>,
+}
+impl Caller {
+    /// The staff member running a [`Privilege::Staff`] command.
+    pub fn staff(&self) -> &str {
+        self.staff
+            .as_deref()
+            .expect("staff command run without logging in")
+    }
+}
+
+pub enum Handler {
+    /// Runs against the whole program. Gets the staff member who logged in,
+    /// if any.
+    Global(fn(&mut Queue, Option<&str>, &[String]) -> CommandResult),
+    /// Runs against a single course. The course is taken from the first
+    /// argument if it names one, otherwise from the `use` context.
+    Course(fn(&mut QueueState, &mut Caller, &[String]) -> CommandResult),
+    /// Runs for a long time or asks questions at the terminal, and locks
+    /// the queue only when it needs it, so the HTTP API keeps working
+    /// meanwhile.
+    Shared(fn(&Mutex<Queue>, Option<&str>, &[String]) -> CommandResult),
+    /// Runs against a single course like [`Handler::Course`], for commands
+    /// that ask questions at the terminal. The queue is locked only to check
+    /// and record, with [`lock_course`], never while someone types.
+    Prompted(fn(&Mutex<Queue>, &str, &mut Caller, &[String]) -> CommandResult),
+}
+
+/// Runs `run` on a course with the queue locked.
+pub fn lock_course<T>(
+    queue: &Mutex<Queue>,
+    course_id: &str,
+    run: impl FnOnce(&mut QueueState) -> T,
+) -> T {
+    run(queue.lock().unwrap().courses.get_mut(course_id).unwrap())
+}
+
+/// Runs `action` for a student at the kiosk, once they are known to be in
+/// the queue and proved who they are.
+fn as_student(
+    queue: &Mutex<Queue>,
+    course_id: &str,
+    net_id: &str,
+    action: fn(&mut QueueState, &str) -> CommandResult,
+) -> CommandResult {
+    lock_course(queue, course_id, |course| {
+        course.ensure_queued(net_id).map(|_| ())
+    })?;
+    verify_at_terminal(queue, course_id, net_id)?;
+    lock_course(queue, course_id, |course| action(course, net_id))
+}
+
+pub struct Command {
+    pub name: &'static str,
+    /// Arguments after the course, `<required>` or `[optional]`. The last
+    /// one may end in "..." to take the rest of the line.
+    pub args: &'static [&'static str],
+    pub privilege: Privilege,
+    pub help: &'static str,
+    pub handler: Handler,
+}
+impl Command {
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_owned();
+        if let Handler::Course(_) | Handler::Prompted(_) = self.handler {
+            usage.push_str(" [course]");
+        }
+        for arg in self.args {
+            usage.push(' ');
+            usage.push_str(arg);
+        }
+        usage
+    }
+    fn check_args(&self, args: &[String]) -> CommandResult {
+        let required = self.args.iter().filter(|arg| arg.starts_with('<')).count();
+        // A trailing "..." argument takes the rest of the line.
+        let variadic = self
+            .args
+            .last()
+            .is_some_and(|arg| arg.trim_end_matches([']', '>']).ends_with("..."));
+        if args.len() < required || (!variadic && args.len() > self.args.len()) {
+            return Err(format!("Usage: \"{}\".", self.usage()));
+        }
+        Ok(())
+    }
+}
+
+/// Every command the terminal understands, in the order `help` lists them.
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        args: &["[command]"],
+        privilege: Privilege::Public,
+        help: "Lists every command, or explains a single one.",
+        handler: Handler::Global(|queue, _, args| queue.help(args.first().map(String::as_str))),
+    },
+    Command {
+        name: "courses",
+        args: &[],
+        privilege: Privilege::Public,
+        help: "Lists the courses and the names they can be referred to by.",
+        handler: Handler::Global(|queue, _, _| queue.list_courses()),
+    },
+    Command {
+        name: "add",
+        args: &["<netid>"],
+        privilege: Privilege::Public,
+        help: "Adds the netid to the queue. Asks for a PIN, student ID digits or a card swipe \
+               if the course wants one, then for a topic and notes on what you need.",
+        handler: Handler::Prompted(|queue, course_id, _, args| {
+            // No point asking for a PIN to then say the queue is locked.
+            lock_course(queue, course_id, |course| course.check_join(&args[0]))?;
+            verify_at_terminal(queue, course_id, &args[0])?;
+            let question = prompt_question(queue, course_id)?;
+            lock_course(queue, course_id, |course| course.add(&args[0], question))
+        }),
+    },
+    Command {
+        name: "leave",
+        args: &["<netid>"],
+        privilege: Privilege::Public,
+        help: "Takes the netid out of the queue.",
+        handler: Handler::Prompted(|queue, course_id, _, args| {
+            as_student(queue, course_id, &args[0], QueueState::leave)
+        }),
+    },
+    Command {
+        name: "away",
+        args: &["<netid>"],
+        privilege: Privilege::Public,
+        help: "Steps away from the queue, holding your spot. Staff skip you until you are back.",
+        handler: Handler::Prompted(|queue, course_id, _, args| {
+            as_student(queue, course_id, &args[0], QueueState::step_away)
+        }),
+    },
+    Command {
+        name: "back",
+        args: &["<netid>"],
+        privilege: Privilege::Public,
+        help: "Returns to the spot you held with \"away\".",
+        handler: Handler::Prompted(|queue, course_id, _, args| {
+            as_student(queue, course_id, &args[0], QueueState::come_back)
+        }),
+    },
+    Command {
+        name: "here",
+        args: &["<netid>"],
+        privilege: Privilege::Public,
+        help: "Confirms you are there after being called.",
+        handler: Handler::Prompted(|queue, course_id, _, args| {
+            as_student(queue, course_id, &args[0], QueueState::confirm)
+        }),
+    },
+    Command {
+        name: "status",
+        args: &["<netid>"],
+        privilege: Privilege::Public,
+        help: "Shows where the netid is in line and the estimated wait.",
+        handler: Handler::Course(|course, _, args| course.status(&args[0])),
+    },
+    Command {
+        name: "admit",
+        args: &["<netid>"],
+        privilege: Privilege::Staff,
+        help: "Adds the netid to the queue even if they reached the visit limit or are \
+               cooling down after their last visit.",
+        handler: Handler::Prompted(|queue, course_id, caller, args| {
+            lock_course(queue, course_id, |course| course.check_admit(&args[0]))?;
+            let question = prompt_question(queue, course_id)?;
+            lock_course(queue, course_id, |course| {
+                course.admit(&args[0], question, caller.staff())
+            })
+        }),
+    },
+    Command {
+        name: "view",
+        args: &[],
+        privilege: Privilege::Public,
+        help: "Views the queue.",
+        handler: Handler::Course(|course, _, _| course.view()),
+    },
+    Command {
+        name: "use",
+        args: &["[course]"],
+        privilege: Privilege::Staff,
+        help: "Makes commands default to the course when they don't name one. \
+               Without a course, forgets the current one.",
+        handler: Handler::Global(|queue, _, args| {
+            queue.use_course(args.first().map(String::as_str))
+        }),
+    },
+    Command {
+        name: "checkin",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Checks in the staff member who logs in.",
+        handler: Handler::Course(|course, caller, _| course.checkin(caller.staff())),
+    },
+    Command {
+        name: "checkout",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Checks out the staff member who logs in.",
+        handler: Handler::Course(|course, caller, _| course.checkout(caller.staff())),
+    },
+    Command {
+        name: "pop",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Removes the next student from the queue, skipping those who stepped away. \
+               Staff have to be checked in.",
+        handler: Handler::Course(|course, caller, _| course.pop(caller.staff())),
+    },
+    Command {
+        name: "claim",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Takes the next student from the queue, or the one you called, and marks them \
+               as being helped by you.",
+        handler: Handler::Course(|course, caller, _| course.claim(caller.staff())),
+    },
+    Command {
+        name: "call",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Calls the next student, who has a few minutes to confirm with \"here\". \
+               If they don't, they move back, and are dropped after missing too many calls.",
+        handler: Handler::Course(|course, caller, _| course.call(caller.staff())),
+    },
+    Command {
+        name: "done",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Finishes helping the student you claimed and records how long it took.",
+        handler: Handler::Course(|course, caller, _| course.done(caller.staff())),
+    },
+    Command {
+        name: "requeue",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Puts the student you claimed back at the front of the queue.",
+        handler: Handler::Course(|course, caller, _| course.requeue(caller.staff())),
+    },
+    Command {
+        name: "dashboard",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Opens a full-screen, live view of every course with keys to pop, claim, \
+               and lock or unlock. Press q to leave it.",
+        handler: Handler::Shared(|queue, staff, _| dashboard::run(queue, staff.unwrap())),
+    },
+    Command {
+        name: "staff_report",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Shows how many students each staff member helped and their average wait.",
+        handler: Handler::Course(|course, _, _| course.staff_report()),
+    },
+    Command {
+        name: "no_shows",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Lists the students who missed calls, most missed first.",
+        handler: Handler::Course(|course, _, _| course.no_shows()),
+    },
+    Command {
+        name: "topic_report",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Breaks down the visits by topic: how they ended, average wait and help, \
+               and how many are waiting now.",
+        handler: Handler::Course(|course, _, _| course.topic_report()),
+    },
+    Command {
+        name: "schedule",
+        args: &[],
+        privilege: Privilege::Public,
+        help: "Shows the current session and the ones in the next week.",
+        handler: Handler::Course(|course, _, _| course.print_schedule()),
+    },
+    Command {
+        name: "session_report",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Breaks down the visits by scheduled session: how they ended and average \
+               wait and help.",
+        handler: Handler::Course(|course, _, _| course.session_report()),
+    },
+    Command {
+        name: "lock",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Locks the queue so nobody else can join.",
+        handler: Handler::Course(|course, _, _| course.lock()),
+    },
+    Command {
+        name: "unlock",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Unlocks the queue.",
+        handler: Handler::Course(|course, _, _| course.unlock()),
+    },
+    Command {
+        name: "close",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Locks the queue and checks out all staff.",
+        handler: Handler::Course(|course, _, _| course.close()),
+    },
+    Command {
+        name: "hours",
+        args: &["<from>", "<to>", "[csv_file]"],
+        privilege: Privilege::Staff,
+        help:
+            "Shows the hours each staff member worked between two dates (YYYY-MM-DD, inclusive), \
+               optionally exporting every shift as CSV.",
+        handler: Handler::Course(|course, _, args| {
+            course.hours(&args[0], &args[1], args.get(2).map(String::as_str))
+        }),
+    },
+    Command {
+        name: "stats",
+        args: &["<filename>"],
+        privilege: Privilege::Staff,
+        help: "Dumps the stats to a file.",
+        handler: Handler::Course(|course, _, args| course.stats(&args[0])),
+    },
+    Command {
+        name: "reset",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Empties the queue and resets the stats.",
+        handler: Handler::Course(|course, _, _| course.reset()),
+    },
+    Command {
+        name: "save",
+        args: &["<filename>"],
+        privilege: Privilege::Staff,
+        help: "Saves the course's state to a file.",
+        handler: Handler::Course(|course, _, args| course.save(&args[0])),
+    },
+    Command {
+        name: "load",
+        args: &["<filename>"],
+        privilege: Privilege::Staff,
+        help: "Loads the course's state from a file.",
+        handler: Handler::Course(|course, _, args| course.load(&args[0])),
+    },
+    Command {
+        name: "add_staff",
+        args: &["<netid>"],
+        privilege: Privilege::Staff,
+        help: "Adds a staff member, asking for their password if they don't have one yet.",
+        handler: Handler::Prompted(|queue, course_id, _, args| {
+            let net_id = args[0].to_lowercase();
+            let needs_password = {
+                let queue = queue.lock().unwrap();
+                queue.courses[course_id].check_new_staff(&net_id)?;
+                !queue.credentials.contains(&net_id)
+            };
+            if needs_password {
+                let password = prompt_new_password(&net_id)?;
+                queue
+                    .lock()
+                    .unwrap()
+                    .credentials
+                    .set_password(&net_id, &password)?;
+            }
+            lock_course(queue, course_id, |course| course.add_staff(&net_id))
+        }),
+    },
+    Command {
+        name: "issue_pin",
+        args: &["<netid>"],
+        privilege: Privilege::Staff,
+        help: "Gives the student a new PIN, for courses that verify students by PIN.",
+        handler: Handler::Course(|course, _, args| course.issue_pin(&args[0])),
+    },
+    Command {
+        name: "clear_lockout",
+        args: &["<netid>"],
+        privilege: Privilege::Staff,
+        help: "Lets a student locked out after too many wrong PINs, student ID digits or card \
+               swipes try again.",
+        handler: Handler::Course(|course, _, args| course.clear_lockout(&args[0])),
+    },
+    Command {
+        name: "accommodation",
+        args: &["<netid>", "<on|off>"],
+        privilege: Privilege::Staff,
+        help: "Gives the student priority, or takes it away, for courses ordered by accommodation.",
+        handler: Handler::Course(|course, _, args| course.set_accommodation(&args[0], &args[1])),
+    },
+    Command {
+        name: "load_roster",
+        args: &["<path_to_file>", "[format]", "[--replace]"],
+        privilege: Privilege::Staff,
+        help: "Merges a roster CSV into the current one after showing what changes. \
+               --replace overwrites it, history and all. Formats are legacy (last,first,netid), \
+               canvas, gradescope and registrar, the course's configured format by default.",
+        handler: Handler::Prompted(|queue, course_id, _, args| {
+            let Some(roster) = lock_course(queue, course_id, |course| course.prepare_roster(args))?
+            else {
+                return Ok(());
+            };
+            if !confirm("Apply these changes?") {
+                return Err("Roster not loaded.".to_owned());
+            }
+            lock_course(queue, course_id, |course| course.load_roster(roster))
+        }),
+    },
+    Command {
+        name: "fsck",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Checks the queue against the roster and staff, and repairs orphaned or \
+               duplicate entries once confirmed.",
+        handler: Handler::Prompted(|queue, course_id, _, _| {
+            let problems = lock_course(queue, course_id, |course| course.fsck());
+            if problems.is_empty() {
+                return Ok(());
+            }
+            if !confirm("Repair?") {
+                return Err("Nothing repaired.".to_owned());
+            }
+            lock_course(queue, course_id, |course| course.repair(problems))
+        }),
+    },
+    Command {
+        name: "replay",
+        args: &["[--until <time>...]"],
+        privilege: Privilege::Staff,
+        help: "Shows the queue as it was at a past moment, rebuilt from the journal. \
+               Times are \"YYYY-MM-DD HH:MM\" or \"HH:MM\" today.",
+        handler: Handler::Course(|course, _, args| course.replay_until(args)),
+    },
+    Command {
+        name: "passwd",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Changes the password of the staff member who logs in.",
+        handler: Handler::Shared(|queue, staff, _| {
+            let staff = staff.unwrap();
+            let password = prompt_new_password(staff)?;
+            queue
+                .lock()
+                .unwrap()
+                .credentials
+                .set_password(staff, &password)?;
+            println!("Password changed.");
+            Ok(())
+        }),
+    },
+    Command {
+        name: "token",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Creates an HTTP API token for the staff member who logs in.",
+        handler: Handler::Global(|queue, staff, _| {
+            let token = queue
+                .credentials
+                .issue_token(staff.unwrap(), queue.token_lifetime)?;
+            println!("Token: {}", token);
+            println!("Send it as \"Authorization: Bearer <token>\". It won't be shown again.");
+            Ok(())
+        }),
+    },
+    Command {
+        name: "clear",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Clears the screen.",
+        handler: Handler::Global(|_, _, _| {
+            if clearscreen::clear().is_err() {
+                return Err("Failed to clear screen.".to_owned());
+            }
+            Ok(())
+        }),
+    },
+    Command {
+        name: "quit",
+        args: &[],
+        privilege: Privilege::Staff,
+        help: "Checks out all staff, saves every course and exits.",
+        handler: Handler::Global(|queue, _, _| queue.quit()),
+    },
+];
+
+pub fn find_command(name: &str) -> Option<&'static Command> {
+    COMMANDS
+        .iter()
+        .find(|command| command.name.eq_ignore_ascii_case(name))
+}
+
+/// Parses and runs a line typed at the terminal.
+///
+/// The queue is locked while the command runs, but not while a staff member
+/// types their password, nor while [`Handler::Prompted`] and
+/// [`Handler::Shared`] commands wait for an answer.
+///
+/// `<command> [course] <args>...`
+pub fn process_command(queue: &Mutex<Queue>, command: &str) -> CommandResult {
+    let parts = command
+        .split_ascii_whitespace()
+        .map(|s| s.to_owned())
+        .collect::<Vec<String>>();
+    if parts.is_empty() {
+        return Err("Command is empty.".to_owned());
+    }
+
+    let Some(command) = find_command(&parts[0]) else {
+        return Err("Unknown command. Type \"help\" for a list of commands.".to_owned());
+    };
+    let mut args = &parts[1..];
+
+    let course_id = match command.handler {
+        Handler::Course(_) | Handler::Prompted(_) => {
+            let queue = queue.lock().unwrap();
+            match args.first().and_then(|arg| queue.find_course(arg)) {
+                Some(course_id) => {
+                    args = &args[1..];
+                    Some(course_id.to_owned())
+                }
+                None => Some(queue.default_course()?),
+            }
+        }
+        _ => None,
+    };
+    command.check_args(args)?;
+
+    let login = match command.privilege {
+        Privilege::Public => None,
+        Privilege::Staff => Some(credentials::prompt_login()),
+    };
+
+    let mut queue_guard = queue.lock().unwrap();
+    let staff = match &login {
+        Some(login) => Some(queue_guard.authenticate(login, course_id.as_deref())?),
+        None => None,
+    };
+
+    match command.handler {
+        Handler::Global(run) => run(&mut queue_guard, staff.as_deref(), args),
+        Handler::Course(run) => {
+            let mut caller = Caller { staff };
+            let course = queue_guard.courses.get_mut(&course_id.unwrap()).unwrap();
+            run(course, &mut caller, args)
+        }
+        Handler::Shared(run) => {
+            drop(queue_guard);
+            run(queue, staff.as_deref(), args)
+        }
+        Handler::Prompted(run) => {
+            drop(queue_guard);
+            let mut caller = Caller { staff };
+            run(queue, &course_id.unwrap(), &mut caller, args)
+        }
+    }
+}
+
+impl Queue {
+    /// The course to use when a command doesn't name one.
+    fn default_course(&self) -> Result<String, String> {
+        if let Some(course_id) = &self.context {
+            return Ok(course_id.clone());
+        }
+        if self.courses.len() == 1 {
+            return Ok(self.courses.keys().next().unwrap().clone());
+        }
+        Err(format!(
+            "Specify a course, one of: {}.",
+            self.courses
+                .keys()
+                .cloned()
+                .collect::<Vec<String>>()
+                .join(", ")
+        ))
+    }
+
+    /// Prints help.
+    ///
+    /// `help [command]`
+    pub fn help(&mut self, command: Option<&str>) -> CommandResult {
+        if let Some(name) = command {
+            let Some(command) = find_command(name) else {
+                return Err(format!("Unknown command \"{}\".", name));
+            };
+            println!("\"{}\" - {}", command.usage(), command.help);
+            if command.privilege == Privilege::Staff {
+                println!("Requires a staff login.");
+            }
+            return Ok(());
+        }
+
+        for command in COMMANDS {
+            let staff = match command.privilege {
+                Privilege::Public => "",
+                Privilege::Staff => " (staff)",
+            };
+            println!("\"{}\"{} - {}", command.usage(), staff, command.help);
+        }
+        println!();
+        println!("[course] can be left out after \"use <course>\". Type \"courses\" to list them.");
+        if let Some(course_id) = &self.context {
+            println!("Currently using {}.", course_id);
+        }
+        Ok(())
+    }
+
+    /// Lists the configured courses.
+    ///
+    /// `courses`
+    pub fn list_courses(&mut self) -> CommandResult {
+        for course in self.courses.values() {
+            let names = course.config.names().cloned().collect::<Vec<String>>();
+            println!("{}: {}", course.config.display_name(), names.join(", "));
+        }
+        Ok(())
+    }
+
+    /// Sets or clears the default course.
+    ///
+    /// `use [course]`
+    pub fn use_course(&mut self, course: Option<&str>) -> CommandResult {
+        let Some(name) = course else {
+            self.context = None;
+            println!("No longer using a course.");
+            return Ok(());
+        };
+        let Some(course_id) = self.find_course(name) else {
+            return Err(format!("Unknown course \"{}\".", name));
+        };
+        let course_id = course_id.to_owned();
+        println!("Using {}.", self.courses[&course_id].config.display_name());
+        self.context = Some(course_id);
+        Ok(())
+    }
+
+    /// Exit the queue, saves every course's state before doing so.
+    ///
+    /// `quit`
+    pub fn quit(&mut self) -> CommandResult {
+        println!("Exiting...");
+        self.shutdown();
+        exit(0);
+    }
+}
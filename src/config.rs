@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::{
+    limits::VisitWindow, ordering::Ordering, persist::Snapshots, roster::RosterFormatConfig,
+    schedule::Schedule, verify::Verification,
+};
+
+/// Default path of the config file, used when none is given on the command line.
+pub const DEFAULT_CONFIG_PATH: &str = "queue.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Where staff password hashes are kept. Defaults to "credentials.json".
+    #[serde(default)]
+    pub credentials_file: Option<String>,
+    /// Every course that gets its own queue.
+    #[serde(rename = "course", default)]
+    pub courses: Vec<CourseConfig>,
+    /// The HTTP API, off unless configured.
+    #[serde(default)]
+    pub http: Option<HttpConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpConfig {
+    /// Address to listen on, e.g. "127.0.0.1:8053".
+    pub listen: String,
+    /// How long a staff token stays valid.
+    #[serde(default = "default_token_days")]
+    pub token_days: u64,
+}
+fn default_token_days() -> u64 {
+    14
+}
+impl Config {
+    /// Reads and parses the config file at `path`.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            return Err(format!("Config file \"{}\" does not exist.", path));
+        };
+
+        let config: Config = match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(err) => return Err(format!("Failed to parse config file: {}", err)),
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Makes sure every course can be addressed unambiguously.
+    fn validate(&self) -> Result<(), String> {
+        if self.courses.is_empty() {
+            return Err("Config does not declare any courses.".to_owned());
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        for course in &self.courses {
+            if course.id.is_empty() {
+                return Err("Course with an empty id.".to_owned());
+            }
+            for name in course.names() {
+                if !seen.insert(name.to_lowercase()) {
+                    return Err(format!("Course name \"{}\" is used more than once.", name));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CourseConfig {
+    /// Unique identifier, e.g. "ics53".
+    pub id: String,
+    /// Human readable name, shown in `help` and `view`. Defaults to the id.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Other names the course can be referred to by, e.g. "53".
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Netids of staff members. They get added to the course on startup,
+    /// more can be added with `add_staff`.
+    #[serde(default)]
+    pub staff: Vec<String>,
+    /// How `load_roster` reads roster files when no format is given: a
+    /// preset's name or a column mapping.
+    #[serde(default)]
+    pub roster_format: RosterFormatConfig,
+    /// Where the queue state is backed up to after every change.
+    pub backup_file: String,
+    /// Append-only log of every change. Defaults to "<backup_file>.journal".
+    #[serde(default)]
+    pub journal_file: Option<String>,
+    /// Directory for timestamped snapshots of the backup file. Defaults to
+    /// "<backup_file>.snapshots" next to the backup file.
+    #[serde(default)]
+    pub snapshot_dir: Option<String>,
+    /// How many snapshots to keep. 0 disables them.
+    #[serde(default = "default_snapshot_count")]
+    pub snapshot_count: usize,
+    /// Minimum number of minutes between two snapshots.
+    #[serde(default = "default_snapshot_interval")]
+    pub snapshot_interval_minutes: u64,
+    /// How students prove who they are when joining. Off by default.
+    #[serde(default)]
+    pub verification: Verification,
+    /// How many trailing digits of the student ID `student_id`
+    /// verification asks for.
+    #[serde(default = "default_student_id_digits")]
+    pub student_id_digits: usize,
+    /// Where PINs are kept for `pin` verification. Defaults to
+    /// "<backup_file>.pins".
+    #[serde(default)]
+    pub pin_file: Option<String>,
+    /// Minutes a called student has to confirm they are there.
+    #[serde(default = "default_grace_minutes")]
+    pub grace_minutes: u64,
+    /// How many places a student who missed their call moves back.
+    #[serde(default = "default_missed_call_places")]
+    pub missed_call_places: usize,
+    /// Missed calls after which a student is dropped from the queue.
+    #[serde(default = "default_missed_call_limit")]
+    pub missed_call_limit: u32,
+    /// What students can pick as the topic of their question, e.g. "HW3".
+    /// Without any, they only leave notes.
+    #[serde(default)]
+    pub topics: Vec<String>,
+    /// Who gets helped first. First come, first served by default.
+    #[serde(default)]
+    pub ordering: Ordering,
+    /// Most times a student can be helped per day or session. Unlimited if
+    /// unset.
+    #[serde(default)]
+    pub max_visits: Option<usize>,
+    /// What `max_visits` counts over.
+    #[serde(default)]
+    pub max_visits_per: VisitWindow,
+    /// Minutes a student has to wait after being helped before joining
+    /// again.
+    #[serde(default)]
+    pub cooldown_minutes: u64,
+    /// When the queue opens and closes by itself. Opened and closed by
+    /// staff if unset.
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+}
+fn default_snapshot_count() -> usize {
+    10
+}
+fn default_snapshot_interval() -> u64 {
+    5
+}
+fn default_student_id_digits() -> usize {
+    4
+}
+fn default_grace_minutes() -> u64 {
+    3
+}
+fn default_missed_call_places() -> usize {
+    3
+}
+fn default_missed_call_limit() -> u32 {
+    2
+}
+impl CourseConfig {
+    /// The id followed by every alias.
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.id).chain(self.aliases.iter())
+    }
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.id)
+    }
+    pub fn journal_file(&self) -> String {
+        match &self.journal_file {
+            Some(journal_file) => journal_file.clone(),
+            None => format!("{}.journal", self.backup_file),
+        }
+    }
+    pub fn pin_file(&self) -> String {
+        match &self.pin_file {
+            Some(pin_file) => pin_file.clone(),
+            None => format!("{}.pins", self.backup_file),
+        }
+    }
+    pub fn snapshots(&self) -> Snapshots {
+        Snapshots::new(
+            &self.backup_file,
+            self.snapshot_dir.as_deref(),
+            self.snapshot_count,
+            Duration::from_secs(self.snapshot_interval_minutes * 60),
+        )
+    }
+}
@@ -0,0 +1,197 @@
+use std::{collections::BTreeMap, io::Write, path::Path, time::Duration};
+
+use argon2::{
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Argon2,
+};
+use chrono::{DateTime, Utc};
+use rpassword::read_password;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::persist;
+
+/// Default path of the credential store.
+pub const DEFAULT_CREDENTIALS_PATH: &str = "credentials.json";
+
+/// Staff passwords, argon2 hashed and key'd by netid. Kept in its own file,
+/// never in a course's state, so backups, journals and stats can be handed
+/// out without leaking anything.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Credentials {
+    hashes: BTreeMap<String, String>,
+    /// API tokens key'd by their SHA-256, so the file can't be used to log
+    /// in. Tokens are random, a fast hash is enough.
+    #[serde(default)]
+    tokens: BTreeMap<String, Token>,
+    #[serde(skip)]
+    path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Token {
+    net_id: String,
+    issued: DateTime<Utc>,
+}
+
+/// A netid and password typed at the terminal, not checked yet.
+pub struct Login {
+    pub net_id: String,
+    pub password: String,
+}
+
+/// Asks for a netid and password at the terminal.
+pub fn prompt_login() -> Login {
+    print!("Netid:");
+    std::io::stdout().flush().unwrap();
+    let mut net_id = String::new();
+    std::io::stdin().read_line(&mut net_id).unwrap();
+    let net_id = net_id.trim().to_lowercase();
+
+    print!("Enter password:");
+    std::io::stdout().flush().unwrap();
+    let password = read_password().unwrap();
+
+    Login { net_id, password }
+}
+
+/// Asks for a new password for `net_id` twice at the terminal.
+pub fn prompt_new_password(net_id: &str) -> Result<String, String> {
+    print!("New password for {}:", net_id);
+    std::io::stdout().flush().unwrap();
+    let password = read_password().unwrap();
+    print!("Repeat password:");
+    std::io::stdout().flush().unwrap();
+    if read_password().unwrap() != password {
+        return Err("Passwords don't match.".to_owned());
+    }
+    Ok(password)
+}
+
+/// Argon2 hash of a password or PIN, in PHC string format.
+pub fn hash_secret(secret: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    match Argon2::default().hash_password(secret.as_bytes(), &salt) {
+        Ok(hash) => Ok(hash.to_string()),
+        Err(err) => Err(format!("Failed to hash: {}", err)),
+    }
+}
+
+/// Whether `secret` matches a hash from [`hash_secret`].
+pub fn verify_secret(hash: &str, secret: &str) -> bool {
+    let Ok(hash) = PasswordHash::new(hash) else {
+        return false;
+    };
+    Argon2::default()
+        .verify_password(secret.as_bytes(), &hash)
+        .is_ok()
+}
+
+fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+impl Credentials {
+    /// Reads the store at `path`. A missing store is empty.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let mut credentials = match std::fs::read_to_string(path) {
+            Ok(contents) => match serde_json::from_str::<Credentials>(&contents) {
+                Ok(credentials) => credentials,
+                Err(err) => return Err(format!("Failed to parse credentials file: {}", err)),
+            },
+            Err(_) => Credentials::default(),
+        };
+        credentials.path = path.to_owned();
+        Ok(credentials)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let Ok(output) = serde_json::to_string_pretty(self) else {
+            return Err("Failed to serialize.".to_owned());
+        };
+        if let Err(err) = persist::write_atomic(Path::new(&self.path), output.as_bytes()) {
+            return Err(format!("Failed to write credentials file: {}", err));
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, net_id: &str) -> bool {
+        self.hashes.contains_key(net_id)
+    }
+
+    /// Hashes and stores a new password for `net_id`.
+    pub fn set_password(&mut self, net_id: &str, password: &str) -> Result<(), String> {
+        if password.is_empty() {
+            return Err("Password can't be empty.".to_owned());
+        }
+        let hash = hash_secret(password)?;
+        self.hashes.insert(net_id.to_owned(), hash);
+        // A new password logs out every device using the old one.
+        self.tokens.retain(|_, token| token.net_id != net_id);
+        self.save()
+    }
+
+    pub fn verify(&self, net_id: &str, password: &str) -> bool {
+        match self.hashes.get(net_id) {
+            Some(hash) => verify_secret(hash, password),
+            None => false,
+        }
+    }
+
+    /// Checks a login. Returns the netid if the password matches.
+    pub fn check(&self, login: &Login) -> Result<String, String> {
+        if !self.verify(&login.net_id, &login.password) {
+            return Err("Invalid netid or password.".to_owned());
+        }
+        Ok(login.net_id.clone())
+    }
+
+    /// Creates a new API token for `net_id`, dropping tokens older than
+    /// `lifetime`. Only its hash is stored, the token itself can't be shown
+    /// again.
+    pub fn issue_token(&mut self, net_id: &str, lifetime: Duration) -> Result<String, String> {
+        let now = Utc::now();
+        self.tokens
+            .retain(|_, token| (now - token.issued).to_std().unwrap_or_default() <= lifetime);
+
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token = bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+
+        self.tokens.insert(
+            hash_token(&token),
+            Token {
+                net_id: net_id.to_owned(),
+                issued: now,
+            },
+        );
+        self.save()?;
+        Ok(token)
+    }
+
+    /// Netid the token belongs to, if it exists and is younger than
+    /// `lifetime`.
+    pub fn token_owner(&self, token: &str, lifetime: Duration) -> Option<String> {
+        let token = self.tokens.get(&hash_token(token))?;
+        let age = (Utc::now() - token.issued).to_std().unwrap_or_default();
+        if age > lifetime {
+            return None;
+        }
+        Some(token.net_id.clone())
+    }
+
+    pub fn revoke_token(&mut self, token: &str) -> Result<(), String> {
+        if self.tokens.remove(&hash_token(token)).is_none() {
+            return Err("Unknown token.".to_owned());
+        }
+        self.save()
+    }
+}
@@ -0,0 +1,313 @@
+use std::{sync::Mutex, time::Duration};
+
+use chrono::{DateTime, Local, Utc};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Wrap},
+    DefaultTerminal, Frame,
+};
+
+use crate::{average, format_duration, start_of_today, CommandResult, Queue, QueueState};
+
+/// How often the wait times are redrawn when nothing is pressed.
+const TICK: Duration = Duration::from_millis(500);
+
+const KEYS: &str =
+    "←/→ course  p pop  n call  c claim  d done  r requeue  l lock/unlock  i check in/out  q quit";
+
+/// Dashboard state that isn't part of any course.
+struct Dashboard {
+    /// Staff member who opened the dashboard. Every action is theirs.
+    staff: String,
+    /// Index of the selected course.
+    selected: usize,
+    /// Result of the last action.
+    status: Result<String, String>,
+}
+
+impl Dashboard {
+    fn draw(&self, frame: &mut Frame, queue: &Queue) {
+        let now = Utc::now();
+        let [header, body, status, keys] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        frame.render_widget(
+            Line::from(format!(
+                " Queue dashboard, logged in as {}  {}",
+                self.staff,
+                now.with_timezone(&Local).format("%H:%M:%S")
+            ))
+            .bold(),
+            header,
+        );
+
+        let columns = Layout::horizontal(
+            queue
+                .courses
+                .values()
+                .map(|_| Constraint::Fill(1))
+                .collect::<Vec<Constraint>>(),
+        )
+        .split(body);
+        for (i, (course, area)) in queue.courses.values().zip(columns.iter()).enumerate() {
+            frame.render_widget(self.course_panel(course, i == self.selected, now), *area);
+        }
+
+        let status_line = match &self.status {
+            Ok(message) => Line::from(format!(" {}", message)).fg(Color::Green),
+            Err(message) => Line::from(format!(" {}", message)).fg(Color::Red),
+        };
+        frame.render_widget(status_line, status);
+        frame.render_widget(Line::from(format!(" {}", KEYS)).dim(), keys);
+    }
+
+    fn course_panel(
+        &self,
+        course: &QueueState,
+        selected: bool,
+        now: DateTime<Utc>,
+    ) -> Paragraph<'static> {
+        let mut title = vec![Span::from(format!(" {} ", course.config.display_name()))];
+        if course.locked {
+            let locked = match &course.lock_reason {
+                Some(reason) => format!("LOCKED ({}) ", reason.label()),
+                None => "LOCKED ".to_owned(),
+            };
+            title.push(Span::from(locked).fg(Color::Red).bold());
+        }
+        let border = if selected {
+            Style::new().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+        } else {
+            Style::new()
+        };
+        let block = Block::bordered()
+            .title(Line::from(title))
+            .border_style(border);
+
+        let mut lines = Vec::new();
+
+        let mut on_duty = course
+            .staff
+            .iter()
+            .filter_map(|(net_id, staff_member)| Some((net_id, staff_member.on_duty_since()?)))
+            .collect::<Vec<_>>();
+        on_duty.sort_by_key(|(_, since)| *since);
+        let on_duty = on_duty
+            .iter()
+            .map(|(net_id, since)| {
+                format!(
+                    "{} ({})",
+                    net_id,
+                    format_duration((now - *since).to_std().unwrap_or_default())
+                )
+            })
+            .collect::<Vec<String>>();
+        lines.push(Line::from(vec![
+            Span::from("On duty: ").bold(),
+            Span::from(if on_duty.is_empty() {
+                "nobody".to_owned()
+            } else {
+                on_duty.join(", ")
+            }),
+        ]));
+
+        let today = start_of_today();
+        let visits = course
+            .students
+            .values()
+            .flat_map(|student| &student.queue_times)
+            .filter(|visit| visit.at >= today && visit.was_helped())
+            .collect::<Vec<_>>();
+        lines.push(Line::from(vec![
+            Span::from("Today: ").bold(),
+            Span::from(format!(
+                "helped {}, average wait {}, waiting {}",
+                visits.len(),
+                average(visits.iter().map(|visit| visit.wait))
+                    .map_or("-".to_owned(), format_duration),
+                course.queue.len()
+            )),
+        ]));
+        lines.push(Line::default());
+
+        for session in &course.helping {
+            lines.push(
+                Line::from(format!(
+                    "Being helped: {} by {} for {} {}",
+                    course.student_name(&session.student.net_id),
+                    session.staff,
+                    format_duration((now - session.claimed_at).to_std().unwrap_or_default()),
+                    session.student.question
+                ))
+                .fg(Color::Yellow),
+            );
+        }
+        if !course.helping.is_empty() {
+            lines.push(Line::default());
+        }
+
+        if course.queue.is_empty() {
+            lines.push(Line::from("Queue is empty.").dim());
+        }
+        for (i, queued) in course.ordered().into_iter().enumerate() {
+            let mut line = Line::from(vec![
+                Span::from(format!("{:>3}  ", i)).dim(),
+                Span::from(course.student_name(&queued.net_id)),
+                Span::from(format!("  {}", format_duration(queued.time_in_queue(now)))).dim(),
+            ]);
+            if let Some(topic) = &queued.question.topic {
+                line.push_span(Span::from(format!("  [{}]", topic)).fg(Color::Cyan));
+            }
+            if let Some(notes) = &queued.question.notes {
+                line.push_span(Span::from(format!("  {}", notes)));
+            }
+            if let Some(call) = &queued.called {
+                line.push_span(
+                    Span::from(format!(
+                        "  called by {}, {} left",
+                        call.staff,
+                        format_duration(course.grace_left(call, now))
+                    ))
+                    .fg(Color::Yellow),
+                );
+            } else if queued.is_away() {
+                line.push_span(Span::from("  stepped away").italic());
+                line = line.dim();
+            }
+            lines.push(line);
+        }
+
+        Paragraph::new(lines)
+            .block(block)
+            .wrap(Wrap { trim: false })
+    }
+
+    /// Runs the action bound to `key` against the selected course.
+    fn act(&mut self, queue: &mut Queue, key: char) {
+        let Some(course) = queue.courses.values_mut().nth(self.selected) else {
+            return;
+        };
+        if !course.staff.contains_key(&self.staff) {
+            self.status = Err(format!(
+                "{} is not staff of {}.",
+                self.staff, course.config.id
+            ));
+            return;
+        }
+
+        let staff = self.staff.as_str();
+        let result = match key {
+            'p' => course.pop(staff).map(|_| "Popped.".to_owned()),
+            'n' => course.call(staff).map(|_| "Called.".to_owned()),
+            'c' => course.claim(staff).map(|_| "Claimed.".to_owned()),
+            'd' => course.done(staff).map(|_| "Done.".to_owned()),
+            'r' => course.requeue(staff).map(|_| "Requeued.".to_owned()),
+            'l' if course.locked => course.unlock().map(|_| "Queue is unlocked.".to_owned()),
+            'l' => course.lock().map(|_| "Queue is locked.".to_owned()),
+            'i' if course.staff[staff].is_on_duty() => {
+                course.checkout(staff).map(|_| "Checked out.".to_owned())
+            }
+            'i' => course.checkin(staff).map(|_| "Checked in.".to_owned()),
+            _ => return,
+        };
+        self.status = result.map(|message| format!("{}: {}", course.config.id, message));
+    }
+}
+
+/// Full-screen view of every course that stays up to date, with keys for
+/// the common staff actions. Every action is taken as `staff`. The queue is
+/// only locked while drawing or acting, so the HTTP API keeps working.
+///
+/// `dashboard`
+pub fn run(queue: &Mutex<Queue>, staff: &str) -> CommandResult {
+    let mut dashboard = {
+        let queue = queue.lock().unwrap();
+        Dashboard {
+            staff: staff.to_owned(),
+            selected: queue
+                .context
+                .as_ref()
+                .and_then(|id| queue.courses.keys().position(|course| course == id))
+                .or_else(|| {
+                    queue
+                        .courses
+                        .values()
+                        .position(|course| course.staff.contains_key(staff))
+                })
+                .unwrap_or(0),
+            status: Ok("Ready.".to_owned()),
+        }
+    };
+
+    let mut terminal = ratatui::init();
+    let result = run_loop(queue, &mut terminal, &mut dashboard);
+    ratatui::restore();
+    result.map_err(|err| format!("Dashboard failed: {}", err))
+}
+
+/// Sum of every course's journal position, changes whenever anything does.
+fn version(queue: &Queue) -> u64 {
+    queue
+        .courses
+        .values()
+        .map(|course| course.journal_seq)
+        .sum()
+}
+
+fn run_loop(
+    queue: &Mutex<Queue>,
+    terminal: &mut DefaultTerminal,
+    dashboard: &mut Dashboard,
+) -> std::io::Result<()> {
+    let mut drawn_version = None;
+    loop {
+        {
+            let queue = queue.lock().unwrap();
+            // The queue methods print their own messages, which scribble
+            // over the screen, also when run from the HTTP API. Draw
+            // everything again after any change.
+            let version = version(&queue);
+            if drawn_version.is_some_and(|drawn| drawn != version) {
+                terminal.clear()?;
+            }
+            drawn_version = Some(version);
+            terminal.draw(|frame| dashboard.draw(frame, &queue))?;
+        }
+
+        if !event::poll(TICK)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        let mut queue = queue.lock().unwrap();
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Left | KeyCode::BackTab => {
+                dashboard.selected = dashboard.selected.saturating_sub(1);
+            }
+            KeyCode::Right | KeyCode::Tab => {
+                dashboard.selected =
+                    (dashboard.selected + 1).min(queue.courses.len().saturating_sub(1));
+            }
+            KeyCode::Char(key) => {
+                dashboard.act(&mut queue, key);
+                // Failed actions print too.
+                terminal.clear()?;
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::time::{Duration, Instant};
+
+use chrono::{Local, Utc};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Paragraph},
+    DefaultTerminal, Frame,
+};
+use tui_big_text::{BigText, PixelSize};
+
+use crate::{
+    config::{Config, CourseConfig},
+    estimate::format_estimate,
+    format_duration, short_name, QueueState,
+};
+
+/// Default time between two reads of the backups.
+const DEFAULT_REFRESH: Duration = Duration::from_secs(5);
+
+/// How much of a student the lobby gets to see.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Privacy {
+    /// First name and last initial.
+    Names,
+    /// Only their place in line.
+    Positions,
+}
+
+struct Display {
+    courses: Vec<CourseConfig>,
+    privacy: Privacy,
+    refresh: Duration,
+    /// Each course as of the last refresh, `None` if its backup couldn't be
+    /// read.
+    states: Vec<Option<QueueState>>,
+}
+
+/// Reads a course's backup without writing anything, unlike
+/// [`QueueState::load_backup`] which repairs what it finds.
+fn read_state(config: &CourseConfig) -> Option<QueueState> {
+    let contents = std::fs::read_to_string(&config.backup_file).ok()?;
+    let mut state: QueueState = serde_json::from_str(&contents).ok()?;
+    state.config = config.clone();
+    Some(state)
+}
+
+impl Display {
+    fn refresh_states(&mut self) {
+        self.states = self.courses.iter().map(read_state).collect();
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header, body] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(frame.area());
+        frame.render_widget(
+            Line::from(format!(
+                " Office hours queue  {}",
+                Local::now().format("%H:%M")
+            ))
+            .bold(),
+            header,
+        );
+
+        let columns = Layout::horizontal(
+            self.courses
+                .iter()
+                .map(|_| Constraint::Fill(1))
+                .collect::<Vec<Constraint>>(),
+        )
+        .split(body);
+        for ((config, state), area) in self.courses.iter().zip(&self.states).zip(columns.iter()) {
+            self.draw_course(frame, config, state.as_ref(), *area);
+        }
+    }
+
+    fn draw_course(
+        &self,
+        frame: &mut Frame,
+        config: &CourseConfig,
+        state: Option<&QueueState>,
+        area: Rect,
+    ) {
+        let block = Block::bordered();
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let [name, status, estimate, list] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Length(4),
+            Constraint::Length(4),
+            Constraint::Min(0),
+        ])
+        .areas(inner);
+
+        let big = |text: String, style: Style| {
+            BigText::builder()
+                .pixel_size(PixelSize::Quadrant)
+                .style(style)
+                .lines(vec![Line::from(text)])
+                .centered()
+                .build()
+        };
+
+        frame.render_widget(big(config.display_name().to_owned(), Style::new()), name);
+
+        let Some(state) = state else {
+            frame.render_widget(Paragraph::new("Queue unavailable.").centered().dim(), list);
+            return;
+        };
+
+        let (text, color) = if state.locked {
+            ("LOCKED".to_owned(), Color::Red)
+        } else {
+            (format!("{} waiting", state.queue.len()), Color::Green)
+        };
+        frame.render_widget(big(text, Style::new().fg(color)), status);
+
+        // What someone joining now would wait, or why they can't join.
+        let estimate_line = match (&state.lock_reason, state.estimated_wait(state.queue.len())) {
+            (Some(reason), _) if state.locked => reason.to_string(),
+            (_, Some(wait)) => format!("Estimated wait: {}", format_estimate(wait)),
+            (_, None) => "Estimated wait: no staff on duty".to_owned(),
+        };
+        frame.render_widget(
+            Paragraph::new(vec![Line::from(estimate_line).bold()]).centered(),
+            estimate,
+        );
+
+        let now = Utc::now();
+        let mut lines = Vec::new();
+        for (i, queued) in state.ordered().into_iter().enumerate() {
+            let who = match self.privacy {
+                Privacy::Names => match state.students.get(&queued.net_id) {
+                    Some(student) => short_name(student),
+                    None => "-".to_owned(),
+                },
+                Privacy::Positions => String::new(),
+            };
+            let estimate = if queued.called.is_some() {
+                "called".to_owned()
+            } else if queued.is_away() {
+                "stepped away".to_owned()
+            } else {
+                state
+                    .estimated_wait(i)
+                    .map(format_estimate)
+                    .unwrap_or_default()
+            };
+            // Notes can be personal, only the topic is shown in public.
+            let topic = queued.question.topic.as_deref().unwrap_or_default();
+            lines.push(Line::from(format!(
+                "{:>3}. {:<16} {:<12} waited {:<10} {}",
+                i + 1,
+                who,
+                topic,
+                format_duration(queued.time_in_queue(now)),
+                estimate
+            )));
+        }
+        frame.render_widget(Paragraph::new(lines).bold(), list);
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
+        let mut last_refresh: Option<Instant> = None;
+        loop {
+            if last_refresh.is_none_or(|last| last.elapsed() >= self.refresh) {
+                self.refresh_states();
+                last_refresh = Some(Instant::now());
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if !event::poll(Duration::from_secs(1))? {
+                continue;
+            }
+            if let Event::Key(key) = event::read()? {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.kind == KeyEventKind::Press
+                    && (ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc))
+                {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Shows the queues on a lobby monitor. Read-only: it only reads the
+/// backups the kiosk writes, and takes no commands.
+///
+/// `queue53 display [--positions] [--refresh <seconds>] [course...]`
+pub fn run(config: Config, args: &[String]) -> Result<(), String> {
+    let usage = "Usage: queue53 display [--positions] [--refresh <seconds>] [course...]";
+
+    let mut privacy = Privacy::Names;
+    let mut refresh = DEFAULT_REFRESH;
+    let mut names = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--positions" => privacy = Privacy::Positions,
+            "--refresh" => {
+                refresh = match args.next().and_then(|seconds| seconds.parse::<u64>().ok()) {
+                    Some(seconds) if seconds > 0 => Duration::from_secs(seconds),
+                    _ => return Err(usage.to_owned()),
+                }
+            }
+            name if name.starts_with("--") => return Err(usage.to_owned()),
+            name => names.push(name.to_owned()),
+        }
+    }
+
+    let courses = if names.is_empty() {
+        config.courses
+    } else {
+        let mut courses = Vec::new();
+        for name in &names {
+            let Some(course) = config
+                .courses
+                .iter()
+                .find(|course| course.names().any(|n| n.eq_ignore_ascii_case(name)))
+            else {
+                return Err(format!("Unknown course \"{}\".", name));
+            };
+            courses.push(course.clone());
+        }
+        courses
+    };
+
+    let mut display = Display {
+        courses,
+        privacy,
+        refresh,
+        states: Vec::new(),
+    };
+    let mut terminal = ratatui::init();
+    let result = display.run(&mut terminal);
+    ratatui::restore();
+    result.map_err(|err| format!("Display failed: {}", err))
+}
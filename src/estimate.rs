@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use crate::{average, QueueState, Visit};
+
+/// How many of the most recent help sessions the estimate averages over.
+const RECENT_VISITS: usize = 20;
+
+/// Assumed length of a help session before any have been timed.
+const DEFAULT_HELP: Duration = Duration::from_secs(5 * 60);
+
+/// Formats an estimate the way people say them, to the minute.
+pub fn format_estimate(estimate: Duration) -> String {
+    let minutes = estimate.as_secs().div_ceil(60);
+    match minutes {
+        0 | 1 => "under a minute".to_owned(),
+        minutes if minutes < 60 => format!("~{} min", minutes),
+        minutes => format!("~{}h {:02}m", minutes / 60, minutes % 60),
+    }
+}
+
+impl QueueState {
+    /// How many staff are checked in.
+    pub fn on_duty_count(&self) -> usize {
+        self.staff
+            .values()
+            .filter(|staff_member| staff_member.is_on_duty())
+            .count()
+    }
+
+    /// Average length of the most recent help sessions, from `claim` to
+    /// `done`.
+    pub fn average_help(&self) -> Duration {
+        let mut visits = self
+            .students
+            .values()
+            .flat_map(|student| &student.queue_times)
+            .filter(|visit| visit.help.is_some())
+            .collect::<Vec<&Visit>>();
+        visits.sort_by_key(|visit| visit.at);
+        let recent = visits.iter().rev().take(RECENT_VISITS);
+        average(recent.filter_map(|visit| visit.help)).unwrap_or(DEFAULT_HELP)
+    }
+
+    /// Roughly how long until the student at `position` (0 is the front)
+    /// gets help: everyone up to and including them takes an average help
+    /// session, shared between the staff on duty. Students ahead who
+    /// stepped away don't count. `None` with nobody on duty.
+    pub fn estimated_wait(&self, position: usize) -> Option<Duration> {
+        let on_duty = self.on_duty_count();
+        if on_duty == 0 {
+            return None;
+        }
+        let ahead = self
+            .ordered()
+            .into_iter()
+            .take(position)
+            .filter(|queued| !queued.is_away())
+            .count();
+        Some(self.average_help() * (ahead as u32 + 1) / on_duty as u32)
+    }
+}
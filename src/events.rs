@@ -0,0 +1,28 @@
+use std::sync::{
+    mpsc::{self, Receiver, Sender},
+    Arc, Mutex,
+};
+
+use crate::journal::JournalEntry;
+
+/// Everyone listening for changes to a course, e.g. the HTTP event streams.
+/// Clones share the same listeners.
+#[derive(Debug, Clone, Default)]
+pub struct Subscribers(Arc<Mutex<Vec<Sender<JournalEntry>>>>);
+impl Subscribers {
+    /// Starts listening. Every entry recorded from now on arrives on the
+    /// receiver, until it is dropped.
+    pub fn subscribe(&self) -> Receiver<JournalEntry> {
+        let (sender, receiver) = mpsc::channel();
+        self.0.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Sends an entry to every listener, forgetting those that left.
+    pub fn publish(&self, entry: &JournalEntry) {
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|sender| sender.send(entry.clone()).is_ok());
+    }
+}
@@ -0,0 +1,234 @@
+use std::{collections::HashSet, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{journal::Event, CommandResult, QueueState};
+
+/// Something in a course's state that breaks an invariant between the
+/// queue, the roster and the staff. Usually left behind by a roster reload
+/// or a hand edited state file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "problem", rename_all = "snake_case")]
+pub enum Problem {
+    /// Queued or being helped, but not on the roster.
+    UnknownStudent { net_id: String },
+    /// In the queue more than once.
+    DuplicateEntry { net_id: String },
+    /// In the queue while also being helped.
+    QueuedWhileHelped { net_id: String },
+    /// A staff member helping more than one student.
+    HelpingSeveral { staff: String },
+    /// A student being helped by someone who isn't on staff.
+    UnknownStaff { staff: String },
+}
+impl Problem {
+    /// What [`QueueState::fix`] does about it.
+    pub fn repair(&self) -> &'static str {
+        match self {
+            Problem::UnknownStudent { .. } => "remove them",
+            Problem::DuplicateEntry { .. } => "keep their first spot",
+            Problem::QueuedWhileHelped { .. } => "take them off the queue",
+            Problem::HelpingSeveral { .. } => "put all but the first back at the front",
+            Problem::UnknownStaff { .. } => "put the student back at the front",
+        }
+    }
+}
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::UnknownStudent { net_id } => write!(
+                f,
+                "{} is queued or being helped but isn't on the roster.",
+                net_id
+            ),
+            Problem::DuplicateEntry { net_id } => {
+                write!(f, "{} is in the queue more than once.", net_id)
+            }
+            Problem::QueuedWhileHelped { net_id } => {
+                write!(f, "{} is in the queue while being helped.", net_id)
+            }
+            Problem::HelpingSeveral { staff } => {
+                write!(f, "{} is helping more than one student.", staff)
+            }
+            Problem::UnknownStaff { staff } => {
+                write!(f, "{} is helping someone but isn't on staff.", staff)
+            }
+        }
+    }
+}
+
+impl QueueState {
+    /// Whether `net_id` is on the roster and may be in the queue.
+    fn is_enrolled(&self, net_id: &str) -> bool {
+        self.students
+            .get(net_id)
+            .is_some_and(|student| !student.dropped)
+    }
+
+    /// Finds every problem with the state, in the order they should be
+    /// fixed in.
+    pub fn check(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        let mut unknown = HashSet::new();
+        let net_ids = self
+            .queue
+            .iter()
+            .map(|queued| &queued.net_id)
+            .chain(self.helping.iter().map(|session| &session.student.net_id));
+        for net_id in net_ids {
+            if !self.is_enrolled(net_id) && unknown.insert(net_id) {
+                problems.push(Problem::UnknownStudent {
+                    net_id: net_id.clone(),
+                });
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut duplicates = HashSet::new();
+        for queued in &self.queue {
+            let net_id = &queued.net_id;
+            if unknown.contains(net_id) {
+                continue;
+            }
+            if !seen.insert(net_id) && duplicates.insert(net_id) {
+                problems.push(Problem::DuplicateEntry {
+                    net_id: net_id.clone(),
+                });
+            }
+        }
+
+        for session in &self.helping {
+            let net_id = &session.student.net_id;
+            if seen.contains(net_id) {
+                problems.push(Problem::QueuedWhileHelped {
+                    net_id: net_id.clone(),
+                });
+            }
+        }
+
+        let mut helping = HashSet::new();
+        let mut several = HashSet::new();
+        for session in &self.helping {
+            if unknown.contains(&session.student.net_id) {
+                continue;
+            }
+            let staff = &session.staff;
+            if !helping.insert(staff) && several.insert(staff) {
+                problems.push(Problem::HelpingSeveral {
+                    staff: staff.clone(),
+                });
+            }
+        }
+        let mut reported = HashSet::new();
+        for session in &self.helping {
+            let staff = &session.staff;
+            if helping.contains(staff) && !self.staff.contains_key(staff) && reported.insert(staff)
+            {
+                problems.push(Problem::UnknownStaff {
+                    staff: staff.clone(),
+                });
+            }
+        }
+
+        problems
+    }
+
+    /// Fixes a problem found by [`QueueState::check`]. Only touches the
+    /// state, so replaying the journal fixes it the same way.
+    pub fn fix(&mut self, problem: &Problem) {
+        match problem {
+            Problem::UnknownStudent { net_id } => {
+                self.queue.retain(|queued| &queued.net_id != net_id);
+                self.helping
+                    .retain(|session| &session.student.net_id != net_id);
+            }
+            Problem::DuplicateEntry { net_id } => {
+                let mut first = true;
+                self.queue.retain(|queued| {
+                    if &queued.net_id != net_id {
+                        return true;
+                    }
+                    std::mem::replace(&mut first, false)
+                });
+            }
+            Problem::QueuedWhileHelped { net_id } => {
+                self.queue.retain(|queued| &queued.net_id != net_id);
+            }
+            Problem::HelpingSeveral { staff } => {
+                let mut first = true;
+                let mut requeued = Vec::new();
+                self.helping.retain(|session| {
+                    if &session.staff != staff || std::mem::replace(&mut first, false) {
+                        return true;
+                    }
+                    requeued.push(session.student.clone());
+                    false
+                });
+                for student in requeued.into_iter().rev() {
+                    self.queue.push_front(student);
+                }
+            }
+            Problem::UnknownStaff { staff } => {
+                let mut requeued = Vec::new();
+                self.helping.retain(|session| {
+                    if &session.staff != staff {
+                        return true;
+                    }
+                    requeued.push(session.student.clone());
+                    false
+                });
+                for student in requeued.into_iter().rev() {
+                    self.queue.push_front(student);
+                }
+            }
+        }
+    }
+
+    /// Prints any problems with the state, pointing at `fsck` to fix them.
+    pub fn warn_problems(&self) {
+        let problems = self.check();
+        if problems.is_empty() {
+            return;
+        }
+
+        println!(
+            "{} has {} problem(s), run \"fsck\" to repair:",
+            self.config.id,
+            problems.len()
+        );
+        for problem in &problems {
+            println!("  {}", problem);
+        }
+    }
+
+    /// Reports problems with the state and how they would be repaired.
+    ///
+    /// `fsck`
+    pub fn fsck(&self) -> Vec<Problem> {
+        let problems = self.check();
+        if problems.is_empty() {
+            println!("No problems found.");
+        }
+        for problem in &problems {
+            println!("{} Will {}.", problem, problem.repair());
+        }
+        problems
+    }
+
+    /// Repairs the problems `fsck` reported, once staff confirmed. Those
+    /// that went away meanwhile are left alone.
+    pub fn repair(&mut self, mut problems: Vec<Problem>) -> CommandResult {
+        let current = self.check();
+        problems.retain(|problem| current.contains(problem));
+        if problems.is_empty() {
+            println!("Nothing left to repair.");
+            return Ok(());
+        }
+
+        let count = problems.len();
+        self.record(Event::Repaired { problems })?;
+        println!("Repaired {} problem(s).", count);
+        Ok(())
+    }
+}
@@ -0,0 +1,493 @@
+use std::{
+    io::Write,
+    sync::{mpsc::RecvTimeoutError, Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    credentials::Login,
+    estimate::format_estimate,
+    short_name,
+    web::{self, Page},
+    Question, Queue, QueueState,
+};
+
+/// How often an idle event stream sends a comment to check the client is
+/// still there.
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// An error response: its status code and a message for the JSON body.
+struct ApiError {
+    status: u16,
+    message: String,
+}
+impl ApiError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+/// Errors from the queue methods are the caller's fault, e.g. joining a
+/// locked queue.
+impl From<String> for ApiError {
+    fn from(message: String) -> Self {
+        Self::new(400, message)
+    }
+}
+
+type ApiResult = Result<Value, ApiError>;
+
+#[derive(Deserialize)]
+struct LoginBody {
+    net_id: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+struct JoinBody {
+    net_id: String,
+    /// PIN, student ID digits or card swipe, if the course asks for one.
+    #[serde(default)]
+    proof: Option<String>,
+    #[serde(default)]
+    topic: Option<String>,
+    #[serde(default)]
+    notes: Option<String>,
+}
+
+/// Starts the HTTP API on its own thread, one more thread per request.
+pub fn serve(queue: Arc<Mutex<Queue>>, listen: &str) -> Result<(), String> {
+    let server = match Server::http(listen) {
+        Ok(server) => server,
+        Err(err) => return Err(format!("Failed to listen on {}: {}", listen, err)),
+    };
+
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let queue = Arc::clone(&queue);
+            std::thread::spawn(move || handle(&queue, request));
+        }
+    });
+    Ok(())
+}
+
+fn handle(queue: &Mutex<Queue>, mut request: Request) {
+    if request.method() == &Method::Get {
+        if let ["api", "courses", course, "events"] = segments(request.url()).as_slice() {
+            let course = course.to_string();
+            return stream_events(queue, request, &course);
+        }
+    }
+
+    let mut body = String::new();
+    if request.as_reader().read_to_string(&mut body).is_err() {
+        let err = ApiError::new(400, "Failed to read request body.");
+        return respond(request, Err(err));
+    }
+
+    if segments(request.url()).first() != Some(&"api") {
+        let page = web::route(
+            queue,
+            request.method(),
+            request.url(),
+            &source(&request),
+            &body,
+        );
+        let response = match page {
+            Page::Html { status, html } => Response::from_string(html)
+                .with_status_code(status)
+                .with_header(
+                    Header::from_bytes("Content-Type", "text/html; charset=utf-8").unwrap(),
+                ),
+            Page::Redirect(location) => Response::from_string("")
+                .with_status_code(303)
+                .with_header(Header::from_bytes("Location", location).unwrap()),
+        };
+        let _ = request.respond(response);
+        return;
+    }
+
+    let result = route(queue, &request, &body);
+    respond(request, result);
+}
+
+fn respond(request: Request, result: ApiResult) {
+    let (status, body) = match result {
+        Ok(body) => (200, body),
+        Err(err) => (err.status, json!({ "error": err.message })),
+    };
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+    let _ = request.respond(response);
+}
+
+fn parse_body<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, ApiError> {
+    serde_json::from_str(body).map_err(|err| ApiError::new(400, format!("Invalid body: {}", err)))
+}
+
+/// The token from an "Authorization: Bearer <token>" header.
+fn bearer_token(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// Checks the request's token the way [`Queue::authenticate`] checks a
+/// login at the terminal. Returns the staff member's netid.
+fn staff(queue: &Queue, request: &Request, course_id: Option<&str>) -> Result<String, ApiError> {
+    let Some(token) = bearer_token(request) else {
+        return Err(ApiError::new(401, "Missing token."));
+    };
+    let Some(net_id) = queue.credentials.token_owner(token, queue.token_lifetime) else {
+        return Err(ApiError::new(401, "Invalid or expired token."));
+    };
+    queue
+        .authorize(&net_id, course_id)
+        .map_err(|err| ApiError::new(403, err))?;
+    Ok(net_id)
+}
+
+/// The path of a URL, split at the slashes.
+pub fn segments(url: &str) -> Vec<&str> {
+    let path = url.split('?').next().unwrap_or(url);
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+/// Where a request came from, to lock out whoever guesses proofs there.
+pub fn source(request: &Request) -> String {
+    request
+        .remote_addr()
+        .map(|address| address.ip().to_string())
+        .unwrap_or_default()
+}
+
+/// The netid a student sent, once they proved they are them. Checks that
+/// they are in the queue first, so a typo isn't counted as a wrong proof.
+fn verified(course: &mut QueueState, body: JoinBody, source: &str) -> Result<String, ApiError> {
+    let net_id = body.net_id.trim().to_lowercase();
+    course.ensure_queued(&net_id)?;
+    course
+        .verify_student(&net_id, body.proof.as_deref(), source)
+        .map_err(|err| ApiError::new(403, err))?;
+    Ok(net_id)
+}
+
+fn route(queue: &Mutex<Queue>, request: &Request, body: &str) -> ApiResult {
+    let segments = segments(request.url());
+    let method = request.method();
+
+    let mut queue = queue.lock().unwrap();
+    match (method, segments.as_slice()) {
+        (Method::Get, ["api", "courses"]) => Ok(courses(&queue)),
+        (Method::Post, ["api", "login"]) => login(&mut queue, parse_body(body)?),
+        (Method::Post, ["api", "logout"]) => {
+            let Some(token) = bearer_token(request) else {
+                return Err(ApiError::new(401, "Missing token."));
+            };
+            queue.credentials.revoke_token(token)?;
+            Ok(json!({}))
+        }
+        (_, ["api", "courses", course, rest @ ..]) => {
+            let Some(course_id) = queue.find_course(course).map(str::to_owned) else {
+                return Err(ApiError::new(
+                    404,
+                    format!("Unknown course \"{}\".", course),
+                ));
+            };
+            // Only checked for the staff endpoints below.
+            let caller = staff(&queue, request, Some(&course_id));
+            let course = queue.courses.get_mut(&course_id).unwrap();
+
+            match (method, rest) {
+                (Method::Get, ["queue"]) => Ok(view(course, caller.is_ok())),
+                (Method::Get, ["position", net_id]) => position(course, &net_id.to_lowercase()),
+                (Method::Post, ["join"]) => {
+                    let body: JoinBody = parse_body(body)?;
+                    let net_id = body.net_id.trim().to_lowercase();
+                    course.check_join(&net_id)?;
+                    course
+                        .verify_student(&net_id, body.proof.as_deref(), &source(request))
+                        .map_err(|err| ApiError::new(403, err))?;
+                    let question = Question {
+                        topic: body.topic,
+                        notes: body.notes,
+                    };
+                    course.add(&net_id, question)?;
+                    position(course, &net_id)
+                }
+                (Method::Post, ["leave"]) => {
+                    let net_id = verified(course, parse_body(body)?, &source(request))?;
+                    course.leave(&net_id)?;
+                    Ok(json!({ "net_id": net_id, "left": true }))
+                }
+                (Method::Post, ["away"]) => {
+                    let net_id = verified(course, parse_body(body)?, &source(request))?;
+                    course.step_away(&net_id)?;
+                    position(course, &net_id)
+                }
+                (Method::Post, ["back"]) => {
+                    let net_id = verified(course, parse_body(body)?, &source(request))?;
+                    course.come_back(&net_id)?;
+                    position(course, &net_id)
+                }
+                (Method::Post, ["here"]) => {
+                    let net_id = verified(course, parse_body(body)?, &source(request))?;
+                    course.confirm(&net_id)?;
+                    Ok(json!({ "net_id": net_id, "confirmed": true }))
+                }
+                (Method::Post, ["pop"]) => pop(course, &caller?),
+                (Method::Post, ["lock"]) => {
+                    caller?;
+                    course.lock()?;
+                    Ok(json!({ "locked": true }))
+                }
+                (Method::Post, ["unlock"]) => {
+                    caller?;
+                    course.unlock()?;
+                    Ok(json!({ "locked": false }))
+                }
+                (Method::Post, ["checkin"]) => {
+                    let staff = caller?;
+                    course.checkin(&staff)?;
+                    Ok(json!({ "net_id": staff, "on_duty": true }))
+                }
+                (Method::Post, ["checkout"]) => {
+                    let staff = caller?;
+                    course.checkout(&staff)?;
+                    Ok(json!({ "net_id": staff, "on_duty": false }))
+                }
+                (Method::Get, ["stats"]) => {
+                    caller?;
+                    serde_json::to_value(&*course)
+                        .map_err(|_| ApiError::new(500, "Failed to serialize."))
+                }
+                _ => Err(ApiError::new(404, "Not found.")),
+            }
+        }
+        _ => Err(ApiError::new(404, "Not found.")),
+    }
+}
+
+/// `GET /api/courses/<course>/events`, a Server-Sent Events stream with one
+/// event for every change to the course, named after the kind of change,
+/// e.g. "added" or "locked". Its data is the queue as `GET .../queue` shows
+/// it after the change. Starts with a "snapshot" event of the current queue.
+fn stream_events(queue: &Mutex<Queue>, request: Request, course: &str) {
+    let (course_id, receiver, is_staff, snapshot) = {
+        let queue = queue.lock().unwrap();
+        let Some(course_id) = queue.find_course(course).map(str::to_owned) else {
+            let err = ApiError::new(404, format!("Unknown course \"{}\".", course));
+            return respond(request, Err(err));
+        };
+        let is_staff = staff(&queue, &request, Some(&course_id)).is_ok();
+        let course = &queue.courses[&course_id];
+        let snapshot = json!({
+            "seq": course.journal_seq,
+            "type": "snapshot",
+            "queue": view(course, is_staff),
+        });
+        (
+            course_id,
+            course.subscribers.subscribe(),
+            is_staff,
+            snapshot,
+        )
+    };
+
+    let mut writer = request.into_writer();
+    let head = "HTTP/1.1 200 OK\r\n\
+                Content-Type: text/event-stream\r\n\
+                Cache-Control: no-cache\r\n\
+                Connection: close\r\n\r\n";
+    let mut send = |message: String| {
+        writer.write_all(message.as_bytes())?;
+        writer.flush()
+    };
+    if send(head.to_owned()).is_err() {
+        return;
+    }
+    if send(format!("event: snapshot\ndata: {}\n\n", snapshot)).is_err() {
+        return;
+    }
+
+    loop {
+        let message = match receiver.recv_timeout(KEEPALIVE) {
+            Ok(entry) => {
+                let kind = serde_json::to_value(&entry.event)
+                    .ok()
+                    .and_then(|event| event["type"].as_str().map(str::to_owned))
+                    .unwrap_or_default();
+                let queue = queue.lock().unwrap();
+                let data = json!({
+                    "seq": entry.seq,
+                    "at": entry.at,
+                    "type": kind,
+                    "queue": view(&queue.courses[&course_id], is_staff),
+                });
+                format!("id: {}\nevent: {}\ndata: {}\n\n", entry.seq, kind, data)
+            }
+            // Lets a client that went away be noticed by the failed write.
+            Err(RecvTimeoutError::Timeout) => ": keepalive\n\n".to_owned(),
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        if send(message).is_err() {
+            return;
+        }
+    }
+}
+
+/// `GET /api/courses`
+fn courses(queue: &Queue) -> Value {
+    let courses = queue
+        .courses
+        .values()
+        .map(|course| {
+            json!({
+                "id": course.config.id,
+                "name": course.config.display_name(),
+                "aliases": course.config.aliases,
+            })
+        })
+        .collect::<Vec<Value>>();
+    json!({ "courses": courses })
+}
+
+/// `POST /api/login` with `{"net_id": ..., "password": ...}`. Returns a
+/// token for the "Authorization: Bearer" header.
+fn login(queue: &mut Queue, body: LoginBody) -> ApiResult {
+    let login = Login {
+        net_id: body.net_id.trim().to_lowercase(),
+        password: body.password,
+    };
+    let net_id = queue
+        .authenticate(&login, None)
+        .map_err(|err| ApiError::new(401, err))?;
+    let token = queue
+        .credentials
+        .issue_token(&net_id, queue.token_lifetime)?;
+    Ok(json!({
+        "net_id": net_id,
+        "token": token,
+        "expires_in_secs": queue.token_lifetime.as_secs(),
+    }))
+}
+
+/// `GET /api/courses/<course>/queue`. Everyone sees first names and last
+/// initials, staff also see netids.
+fn view(course: &QueueState, is_staff: bool) -> Value {
+    let now = Utc::now();
+    let name = |net_id: &str| match course.students.get(net_id) {
+        Some(student) => short_name(student),
+        None => "-".to_owned(),
+    };
+
+    let mut on_duty = course
+        .staff
+        .iter()
+        .filter(|(_, staff_member)| staff_member.is_on_duty())
+        .map(|(net_id, _)| net_id.clone())
+        .collect::<Vec<String>>();
+    on_duty.sort();
+
+    let helping = course
+        .helping
+        .iter()
+        .map(|session| {
+            let mut entry = json!({
+                "name": name(&session.student.net_id),
+                "staff": session.staff,
+                "since": session.claimed_at,
+                "topic": session.student.question.topic,
+            });
+            if is_staff {
+                entry["net_id"] = json!(session.student.net_id);
+                entry["notes"] = json!(session.student.question.notes);
+            }
+            entry
+        })
+        .collect::<Vec<Value>>();
+
+    let queue = course
+        .ordered()
+        .into_iter()
+        .enumerate()
+        .map(|(i, queued)| {
+            let mut entry = json!({
+                "position": i,
+                "name": name(&queued.net_id),
+                "waiting_secs": queued.time_in_queue(now).as_secs(),
+                "estimated_wait_secs": course.estimated_wait(i).map(|wait| wait.as_secs()),
+                "away": queued.is_away(),
+                "called_by": queued.called.as_ref().map(|call| &call.staff),
+                "topic": queued.question.topic,
+            });
+            if is_staff {
+                entry["net_id"] = json!(queued.net_id);
+                entry["notes"] = json!(queued.question.notes);
+            }
+            entry
+        })
+        .collect::<Vec<Value>>();
+
+    json!({
+        "course": course.config.id,
+        "name": course.config.display_name(),
+        "locked": course.locked,
+        "lock_reason": course.lock_reason.as_ref().map(ToString::to_string),
+        "session": course.session.as_ref().filter(|session| !session.closed).map(|session| {
+            json!({ "id": session.id, "end": session.end })
+        }),
+        "on_duty": on_duty,
+        "helping": helping,
+        "queue": queue,
+    })
+}
+
+/// `GET /api/courses/<course>/position/<netid>`, also the response to
+/// joining.
+fn position(course: &QueueState, net_id: &str) -> ApiResult {
+    let Some(position) = course.position_of(net_id) else {
+        return Err(ApiError::new(
+            404,
+            format!("{} is not in the queue.", net_id),
+        ));
+    };
+    let queued = course.ordered()[position];
+    let wait = course.estimated_wait(position);
+    Ok(json!({
+        "net_id": net_id,
+        "position": position,
+        "waiting_secs": queued.time_in_queue(Utc::now()).as_secs(),
+        "estimated_wait_secs": wait.map(|wait| wait.as_secs()),
+        "estimated_wait": wait.map(format_estimate),
+        "away": queued.is_away(),
+        "called_by": queued.called.as_ref().map(|call| &call.staff),
+    }))
+}
+
+/// `POST /api/courses/<course>/pop`
+fn pop(course: &mut QueueState, staff: &str) -> ApiResult {
+    let front = course.next_up().ok().cloned();
+    course.pop(staff)?;
+
+    // pop only succeeds with someone to take.
+    let front = front.unwrap();
+    Ok(json!({
+        "net_id": front.net_id,
+        "name": course.student_name(&front.net_id),
+        "waited_secs": front.time_in_queue(Utc::now()).as_secs(),
+    }))
+}
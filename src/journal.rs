@@ -0,0 +1,932 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    capacity::LockReason, fsck::Problem, schedule::Session, Call, HelpSession, Outcome, Question,
+    QueueState, QueuedStudent, Shift, StaffMember, Student, Visit,
+};
+
+/// A single change to a course's state.
+///
+/// Applying the same events in the same order always produces the same
+/// state, so everything an event needs has to be in it or in its
+/// [`JournalEntry`], never read from the clock.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// The whole state was replaced, by `load` or when a journal is started
+    /// for a state that already exists.
+    StateLoaded {
+        state: Box<QueueState>,
+    },
+    CheckedIn {
+        net_id: String,
+    },
+    CheckedOut {
+        net_id: String,
+    },
+    /// Shifts left open by a run that didn't shut down cleanly were closed
+    /// as of `end`, its last change.
+    ShiftsClosed {
+        end: DateTime<Utc>,
+    },
+    /// The queue was locked for the day and all staff checked out.
+    Closed,
+    /// The schedule opened the queue for a session.
+    Opened {
+        session: String,
+        end: DateTime<Utc>,
+    },
+    /// The schedule locked the queue for the end of the session.
+    SessionEnding,
+    /// The queue locked because the staff on duty can't help everyone
+    /// waiting before the session ends.
+    Full {
+        reason: LockReason,
+    },
+    /// The schedule locked the queue because no session is open, until the
+    /// one starting at `next`.
+    OutsideHours {
+        next: Option<DateTime<Utc>>,
+    },
+    Added {
+        net_id: String,
+        #[serde(default, skip_serializing_if = "Question::is_empty")]
+        question: Question,
+        /// Staff member who let them in past the visit limits.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        admitted_by: Option<String>,
+    },
+    /// A student took themselves out of the queue.
+    Left {
+        net_id: String,
+    },
+    /// A student stepped away, holding their spot.
+    SteppedAway {
+        net_id: String,
+    },
+    /// A student who stepped away is back.
+    Returned {
+        net_id: String,
+    },
+    /// A staff member called the student, who has a few minutes to show.
+    Called {
+        net_id: String,
+        staff: String,
+    },
+    /// A called student didn't show up in time and moved back.
+    MissedCall {
+        net_id: String,
+        places: usize,
+        /// The students who were next after them, who now go first. Missing
+        /// in old journals, which moved them back in the queue itself.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        behind: Vec<String>,
+    },
+    /// A called student didn't show up once too often and was dropped.
+    NoShow {
+        net_id: String,
+    },
+    Popped {
+        net_id: String,
+        /// Staff member who popped them, missing in old journals.
+        #[serde(default)]
+        staff: Option<String>,
+    },
+    /// A staff member took the student off the queue to help them.
+    Claimed {
+        net_id: String,
+        staff: String,
+    },
+    /// A staff member finished helping the student they claimed.
+    Done {
+        staff: String,
+    },
+    /// A staff member put the student they claimed back at the front.
+    Requeued {
+        staff: String,
+    },
+    Locked,
+    Unlocked,
+    Reset,
+    StaffAdded {
+        net_id: String,
+    },
+    /// The roster was replaced, history and all.
+    RosterLoaded {
+        students: HashMap<String, Student>,
+    },
+    /// A new roster was merged into the current one.
+    RosterMerged {
+        students: HashMap<String, Student>,
+    },
+    /// A student was given priority under the "accommodation" ordering, or
+    /// had it taken away.
+    AccommodationSet {
+        net_id: String,
+        on: bool,
+    },
+    /// Problems found by `fsck` were fixed.
+    Repaired {
+        problems: Vec<Problem>,
+    },
+}
+
+/// One line of the journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Position in the journal, starting at 1.
+    pub seq: u64,
+    /// When the event happened.
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Appends an entry to the journal and makes sure it hit the disk.
+pub fn append(path: &Path, entry: &JournalEntry) -> io::Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    file.sync_data()
+}
+
+/// Reads every entry in the journal. A missing journal is empty.
+///
+/// A crash while appending can leave a partial last line. It gets cut off so
+/// later appends start on a clean line. A bad line anywhere else is an
+/// error.
+pub fn read(path: &Path) -> io::Result<Vec<JournalEntry>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut entries = Vec::new();
+    let mut valid_len = 0;
+    for line in contents.split_inclusive('\n') {
+        match serde_json::from_str::<JournalEntry>(line.trim_end()) {
+            Ok(entry) if line.ends_with('\n') => {
+                entries.push(entry);
+                valid_len += line.len();
+            }
+            _ => break,
+        }
+    }
+
+    if valid_len < contents.len() {
+        let rest = &contents[valid_len..];
+        if rest.trim_end().contains('\n') {
+            // Not just a torn last line, don't throw away what follows.
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "corrupt entry after #{}",
+                    entries.last().map(|entry| entry.seq).unwrap_or(0)
+                ),
+            ));
+        }
+
+        println!(
+            "Journal {} ends in a partial entry, dropping it.",
+            path.display()
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(valid_len as u64)?;
+    }
+    Ok(entries)
+}
+
+impl QueueState {
+    /// Records a change: appends it to the journal, applies it, saves a
+    /// backup and tells the subscribers. Nothing is applied if the journal
+    /// can't be written.
+    pub fn record(&mut self, event: Event) -> Result<(), String> {
+        let entry = JournalEntry {
+            seq: self.journal_seq + 1,
+            at: Utc::now(),
+            event,
+        };
+
+        if let Err(err) = append(Path::new(&self.config.journal_file()), &entry) {
+            return Err(format!("Failed to write journal: {}", err));
+        }
+
+        self.apply(&entry);
+        self.save_backup();
+        self.subscribers.publish(&entry);
+        Ok(())
+    }
+
+    /// Applies a single journal entry.
+    pub fn apply(&mut self, entry: &JournalEntry) {
+        self.apply_event(entry);
+        // Also for entries that found nothing to change, so the next one
+        // recorded doesn't reuse their seq.
+        self.journal_seq = entry.seq;
+    }
+    fn apply_event(&mut self, entry: &JournalEntry) {
+        match &entry.event {
+            Event::StateLoaded { state } => self.restore((**state).clone()),
+            Event::CheckedIn { net_id } => {
+                if let Some(staff_member) = self.staff.get_mut(net_id) {
+                    staff_member.shifts.push(Shift {
+                        start: entry.at,
+                        end: None,
+                    });
+                }
+            }
+            Event::CheckedOut { net_id } => {
+                if let Some(staff_member) = self.staff.get_mut(net_id) {
+                    staff_member.check_out(entry.at);
+                }
+            }
+            Event::ShiftsClosed { end } => {
+                for staff_member in self.staff.values_mut() {
+                    if let Some(since) = staff_member.on_duty_since() {
+                        staff_member.check_out((*end).max(since));
+                    }
+                }
+            }
+            Event::Closed => {
+                self.locked = true;
+                self.lock_reason = None;
+                self.closed_at = Some(entry.at);
+                if let Some(session) = &mut self.session {
+                    session.closed = true;
+                }
+                for staff_member in self.staff.values_mut() {
+                    staff_member.check_out(entry.at);
+                }
+            }
+            Event::Opened { session, end } => {
+                self.locked = false;
+                self.lock_reason = None;
+                self.session = Some(Session {
+                    id: session.clone(),
+                    start: entry.at,
+                    end: *end,
+                    ending: false,
+                    closed: false,
+                    full: false,
+                });
+            }
+            Event::SessionEnding => {
+                self.locked = true;
+                if let Some(session) = &mut self.session {
+                    session.ending = true;
+                    self.lock_reason = Some(LockReason::SessionEnding { end: session.end });
+                }
+            }
+            Event::Full { reason } => {
+                self.locked = true;
+                self.lock_reason = Some(reason.clone());
+                if let Some(session) = &mut self.session {
+                    session.full = true;
+                }
+            }
+            Event::OutsideHours { next } => {
+                self.locked = true;
+                self.lock_reason = Some(LockReason::OutsideHours { next: *next });
+            }
+            Event::Added {
+                net_id, question, ..
+            } => self.queue.push_back(QueuedStudent {
+                entry_time: entry.at,
+                net_id: net_id.clone(),
+                away_since: None,
+                away: Duration::ZERO,
+                called: None,
+                missed: 0,
+                question: question.clone(),
+                session: self.session_id(),
+                behind: Vec::new(),
+                requeued: false,
+            }),
+            Event::Left { net_id } => {
+                let Some(i) = self
+                    .queue
+                    .iter()
+                    .position(|queued| &queued.net_id == net_id)
+                else {
+                    return;
+                };
+                let queued = self.queue.remove(i).unwrap();
+                if let Some(student) = self.students.get_mut(net_id) {
+                    student.queue_times.push(Visit {
+                        wait: queued.time_in_queue(entry.at),
+                        at: entry.at,
+                        staff: None,
+                        help: None,
+                        outcome: Outcome::Left,
+                        away: queued.time_away(entry.at),
+                        question: queued.question,
+                        session: queued.session,
+                    });
+                }
+            }
+            Event::SteppedAway { net_id } => {
+                if let Some(queued) = self.queue.iter_mut().find(|q| &q.net_id == net_id) {
+                    queued.away_since = Some(entry.at);
+                }
+            }
+            Event::Returned { net_id } => {
+                if let Some(queued) = self.queue.iter_mut().find(|q| &q.net_id == net_id) {
+                    queued.away = queued.time_away(entry.at);
+                    queued.away_since = None;
+                }
+            }
+            Event::Called { net_id, staff } => {
+                if let Some(queued) = self.queue.iter_mut().find(|q| &q.net_id == net_id) {
+                    queued.called = Some(Call {
+                        staff: staff.clone(),
+                        at: entry.at,
+                    });
+                }
+            }
+            Event::MissedCall {
+                net_id,
+                places,
+                behind,
+            } => {
+                let Some(i) = self
+                    .queue
+                    .iter()
+                    .position(|queued| &queued.net_id == net_id)
+                else {
+                    return;
+                };
+                let mut queued = self.queue.remove(i).unwrap();
+                queued.called = None;
+                queued.missed += 1;
+                queued.requeued = false;
+                if behind.is_empty() {
+                    self.queue
+                        .insert((i + places).min(self.queue.len()), queued);
+                } else {
+                    queued.behind = behind.clone();
+                    self.queue.insert(i, queued);
+                }
+                if let Some(student) = self.students.get_mut(net_id) {
+                    student.missed_calls.push(entry.at);
+                }
+            }
+            Event::NoShow { net_id } => {
+                let Some(i) = self
+                    .queue
+                    .iter()
+                    .position(|queued| &queued.net_id == net_id)
+                else {
+                    return;
+                };
+                let queued = self.queue.remove(i).unwrap();
+                if let Some(student) = self.students.get_mut(net_id) {
+                    student.missed_calls.push(entry.at);
+                    student.queue_times.push(Visit {
+                        wait: queued.time_in_queue(entry.at),
+                        at: entry.at,
+                        staff: queued.called.as_ref().map(|call| call.staff.clone()),
+                        help: None,
+                        outcome: Outcome::NoShow,
+                        away: queued.time_away(entry.at),
+                        question: queued.question,
+                        session: queued.session,
+                    });
+                }
+            }
+            Event::Popped { net_id, staff } => {
+                let Some(i) = self
+                    .queue
+                    .iter()
+                    .position(|queued| &queued.net_id == net_id)
+                else {
+                    return;
+                };
+                let queued = self.queue.remove(i).unwrap();
+                let time_in_queue = (entry.at - queued.entry_time).to_std().unwrap_or_default();
+                if let Some(student) = self.students.get_mut(net_id) {
+                    student.queue_times.push(Visit {
+                        wait: time_in_queue,
+                        at: entry.at,
+                        staff: staff.clone(),
+                        help: None,
+                        outcome: Outcome::Helped,
+                        away: queued.time_away(entry.at),
+                        question: queued.question,
+                        session: queued.session,
+                    });
+                }
+            }
+            Event::Claimed { net_id, staff } => {
+                let Some(i) = self
+                    .queue
+                    .iter()
+                    .position(|queued| &queued.net_id == net_id)
+                else {
+                    return;
+                };
+                let mut student = self.queue.remove(i).unwrap();
+                student.called = None;
+                self.helping.push(HelpSession {
+                    student,
+                    staff: staff.clone(),
+                    claimed_at: entry.at,
+                });
+            }
+            Event::Done { staff } => {
+                let Some(i) = self
+                    .helping
+                    .iter()
+                    .position(|session| &session.staff == staff)
+                else {
+                    return;
+                };
+                let session = self.helping.remove(i);
+                let wait = (session.claimed_at - session.student.entry_time)
+                    .to_std()
+                    .unwrap_or_default();
+                let help = (entry.at - session.claimed_at).to_std().unwrap_or_default();
+                if let Some(student) = self.students.get_mut(&session.student.net_id) {
+                    student.queue_times.push(Visit {
+                        wait,
+                        at: entry.at,
+                        staff: Some(session.staff),
+                        help: Some(help),
+                        outcome: Outcome::Helped,
+                        away: session.student.away,
+                        question: session.student.question,
+                        session: session.student.session,
+                    });
+                }
+            }
+            Event::Requeued { staff } => {
+                let Some(i) = self
+                    .helping
+                    .iter()
+                    .position(|session| &session.staff == staff)
+                else {
+                    return;
+                };
+                let mut student = self.helping.remove(i).student;
+                student.requeued = true;
+                student.behind.clear();
+                self.queue.push_front(student);
+            }
+            Event::Locked => {
+                self.locked = true;
+                self.lock_reason = None;
+            }
+            Event::Unlocked => {
+                self.locked = false;
+                self.lock_reason = None;
+            }
+            Event::Reset => {
+                for student in self.students.values_mut() {
+                    student.queue_times.clear();
+                    student.missed_calls.clear();
+                }
+                self.queue.clear();
+                self.helping.clear();
+                // Outside its sessions a scheduled course stays locked.
+                if !matches!(self.lock_reason, Some(LockReason::OutsideHours { .. })) {
+                    self.locked = false;
+                    self.lock_reason = None;
+                }
+            }
+            Event::StaffAdded { net_id } => {
+                self.staff.insert(
+                    net_id.clone(),
+                    StaffMember {
+                        shifts: Vec::default(),
+                    },
+                );
+            }
+            Event::RosterLoaded { students } => self.students = students.clone(),
+            Event::RosterMerged { students } => {
+                for (net_id, student) in self.students.iter_mut() {
+                    if !students.contains_key(net_id) {
+                        student.dropped = true;
+                    }
+                }
+                for (net_id, new) in students {
+                    let Some(student) = self.students.get_mut(net_id) else {
+                        self.students.insert(net_id.clone(), new.clone());
+                        continue;
+                    };
+                    student.first = new.first.clone();
+                    student.last = new.last.clone();
+                    student.email = new.email.clone();
+                    student.section = new.section.clone();
+                    student.student_id = new.student_id.clone();
+                    student.dropped = false;
+                }
+            }
+            Event::AccommodationSet { net_id, on } => {
+                if let Some(student) = self.students.get_mut(net_id) {
+                    student.accommodation = *on;
+                }
+            }
+            Event::Repaired { problems } => {
+                for problem in problems {
+                    self.fix(problem);
+                }
+            }
+        }
+    }
+
+    /// Brings the state loaded from the backup up to date with the journal.
+    /// Starts a journal if there isn't one yet.
+    pub fn catch_up(&mut self) {
+        let path = self.config.journal_file();
+        let entries = match read(Path::new(&path)) {
+            Ok(entries) => entries,
+            Err(err) => {
+                println!("Failed to read journal {}: {}", path, err);
+                return;
+            }
+        };
+
+        let last_seq = entries.last().map(|entry| entry.seq).unwrap_or(0);
+        if last_seq < self.journal_seq || entries.is_empty() {
+            // No journal yet, or one that's behind the backup. Start over
+            // from the current state so replaying the journal from the
+            // beginning still works.
+            self.journal_seq = self.journal_seq.max(last_seq);
+            let state = Box::new(self.clone());
+            if let Err(err) = self.record(Event::StateLoaded { state }) {
+                println!("{}", err);
+            }
+            return;
+        }
+
+        let mut replayed = 0;
+        let start = self.journal_seq;
+        for entry in entries.iter().filter(|entry| entry.seq > start) {
+            self.apply(entry);
+            replayed += 1;
+        }
+        if replayed > 0 {
+            println!(
+                "Replayed {} journal entries for {}.",
+                replayed, self.config.id
+            );
+            self.save_backup();
+        }
+    }
+
+    /// When the last change in the journal happened, not counting states
+    /// loaded wholesale.
+    pub fn last_change(&self) -> Option<DateTime<Utc>> {
+        let entries = read(Path::new(&self.config.journal_file())).ok()?;
+        entries
+            .iter()
+            .rev()
+            .find(|entry| !matches!(entry.event, Event::StateLoaded { .. }))
+            .map(|entry| entry.at)
+    }
+
+    /// Adds the staff members listed in the config that aren't on the
+    /// course's staff yet.
+    pub fn seed_staff(&mut self) {
+        for net_id in self.config.staff.clone() {
+            let net_id = net_id.to_lowercase();
+            if self.staff.contains_key(&net_id) {
+                continue;
+            }
+            if let Err(err) = self.record(Event::StaffAdded { net_id }) {
+                println!("{}", err);
+            }
+        }
+    }
+
+    /// Rebuilds the state from nothing by replaying the journal, stopping at
+    /// `until` if given.
+    pub fn replay(&self, until: Option<DateTime<Utc>>) -> Result<QueueState, String> {
+        let path = self.config.journal_file();
+        let entries = match read(Path::new(&path)) {
+            Ok(entries) => entries,
+            Err(err) => return Err(format!("Failed to read journal: {}", err)),
+        };
+
+        let mut state = QueueState::new(self.config.clone());
+        for entry in entries
+            .iter()
+            .take_while(|entry| until.is_none_or(|until| entry.at <= until))
+        {
+            state.apply(entry);
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::config::CourseConfig;
+
+    /// A fresh, empty directory for one test, removed when dropped.
+    struct TempDir(PathBuf);
+    impl TempDir {
+        fn join(&self, name: &str) -> PathBuf {
+            self.0.join(name)
+        }
+    }
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+    fn temp_dir() -> TempDir {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "queue53-journal-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    fn config(dir: &TempDir) -> CourseConfig {
+        CourseConfig {
+            id: "test".to_owned(),
+            backup_file: dir.join("backup.txt").to_string_lossy().into_owned(),
+            snapshot_count: 0,
+            ..CourseConfig::default()
+        }
+    }
+
+    fn entry(seq: u64, minute: u32, event: Event) -> JournalEntry {
+        JournalEntry {
+            seq,
+            at: Utc.with_ymd_and_hms(2026, 10, 15, 14, minute, 0).unwrap(),
+            event,
+        }
+    }
+
+    fn staff_added(seq: u64, minute: u32, net_id: &str) -> JournalEntry {
+        entry(
+            seq,
+            minute,
+            Event::StaffAdded {
+                net_id: net_id.to_owned(),
+            },
+        )
+    }
+
+    fn write_journal(path: &Path, entries: &[JournalEntry]) {
+        for entry in entries {
+            append(path, entry).unwrap();
+        }
+    }
+
+    #[test]
+    fn missing_journal_is_empty() {
+        let dir = temp_dir();
+        assert!(read(&dir.join("none.journal")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn torn_last_line_is_cut_off() {
+        let dir = temp_dir();
+        let path = dir.join("test.journal");
+        write_journal(&path, &[staff_added(1, 0, "ta1"), staff_added(2, 1, "ta2")]);
+        let valid_len = fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"seq":3,"at":"2026-10-15T14:02:00Z","type":"sta"#)
+            .unwrap();
+
+        let entries = read(&path).unwrap();
+        assert_eq!(entries.iter().map(|e| e.seq).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), valid_len);
+
+        // Appends after the cut start on a clean line.
+        append(&path, &staff_added(3, 2, "ta3")).unwrap();
+        assert_eq!(read(&path).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn corrupt_line_mid_file_is_an_error() {
+        let dir = temp_dir();
+        let path = dir.join("test.journal");
+        write_journal(&path, &[staff_added(1, 0, "ta1")]);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"not json\n").unwrap();
+        write_journal(&path, &[staff_added(3, 2, "ta3")]);
+        let len = fs::metadata(&path).unwrap().len();
+
+        let err = read(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("after #1"));
+        // Nothing is thrown away.
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+    }
+
+    #[test]
+    fn replay_stops_at_until() {
+        let dir = temp_dir();
+        let state = QueueState::new(config(&dir));
+        write_journal(
+            Path::new(&state.config.journal_file()),
+            &[
+                staff_added(1, 0, "ta1"),
+                staff_added(2, 10, "ta2"),
+                staff_added(3, 20, "ta3"),
+            ],
+        );
+
+        let until = Utc.with_ymd_and_hms(2026, 10, 15, 14, 10, 0).unwrap();
+        let past = state.replay(Some(until)).unwrap();
+        let mut staff = past.staff.keys().cloned().collect::<Vec<String>>();
+        staff.sort();
+        assert_eq!(staff, ["ta1", "ta2"]);
+        assert_eq!(past.journal_seq, 2);
+
+        let now = state.replay(None).unwrap();
+        assert_eq!(now.staff.len(), 3);
+        assert_eq!(now.journal_seq, 3);
+    }
+
+    #[test]
+    fn entries_that_change_nothing_still_count() {
+        let dir = temp_dir();
+        let mut state = QueueState::new(config(&dir));
+        state.apply(&entry(
+            7,
+            0,
+            Event::Left {
+                net_id: "nobody".to_owned(),
+            },
+        ));
+        assert_eq!(state.journal_seq, 7);
+    }
+
+    #[test]
+    fn catch_up_applies_only_the_tail() {
+        let dir = temp_dir();
+        let mut state = QueueState::new(config(&dir));
+        write_journal(
+            Path::new(&state.config.journal_file()),
+            &[
+                staff_added(1, 0, "ta1"),
+                staff_added(2, 1, "ta2"),
+                staff_added(3, 2, "ta3"),
+            ],
+        );
+        // The backup was saved after entry 2, without ta1 to show entry 1
+        // isn't applied again.
+        state.apply(&staff_added(2, 1, "ta2"));
+
+        state.catch_up();
+        let mut staff = state.staff.keys().cloned().collect::<Vec<String>>();
+        staff.sort();
+        assert_eq!(staff, ["ta2", "ta3"]);
+        assert_eq!(state.journal_seq, 3);
+    }
+
+    #[test]
+    fn catch_up_restarts_a_journal_behind_the_backup() {
+        let dir = temp_dir();
+        let mut state = QueueState::new(config(&dir));
+        let path = PathBuf::from(state.config.journal_file());
+        write_journal(&path, &[staff_added(1, 0, "ta1")]);
+        state.apply(&staff_added(5, 4, "ta5"));
+
+        state.catch_up();
+        assert_eq!(state.journal_seq, 6);
+        let entries = read(&path).unwrap();
+        let last = entries.last().unwrap();
+        assert_eq!(last.seq, 6);
+        assert!(matches!(last.event, Event::StateLoaded { .. }));
+
+        // Replaying from the start ends up where the backup was.
+        let replayed = state.replay(None).unwrap();
+        assert_eq!(replayed.staff.keys().collect::<Vec<_>>(), ["ta5"]);
+    }
+
+    /// A course ordered by accommodation, where `a` has one, with `queued`
+    /// joined in that order.
+    fn accommodation_course(dir: &TempDir, queued: &[&str]) -> QueueState {
+        let mut state = QueueState::new(CourseConfig {
+            ordering: crate::ordering::Ordering::Accommodation,
+            ..config(dir)
+        });
+        let students = serde_json::from_value(serde_json::json!({
+            "a": { "first": "A", "last": "A", "queue_times": [] },
+            "b": { "first": "B", "last": "B", "queue_times": [] },
+            "c": { "first": "C", "last": "C", "queue_times": [] },
+            "d": { "first": "D", "last": "D", "queue_times": [] },
+        }))
+        .unwrap();
+        state.apply(&entry(1, 0, Event::RosterMerged { students }));
+        state.apply(&entry(
+            2,
+            0,
+            Event::AccommodationSet {
+                net_id: "a".to_owned(),
+                on: true,
+            },
+        ));
+        for (i, net_id) in queued.iter().enumerate() {
+            state.apply(&entry(
+                3 + i as u64,
+                1 + i as u32,
+                Event::Added {
+                    net_id: net_id.to_string(),
+                    question: Question::default(),
+                    admitted_by: None,
+                },
+            ));
+        }
+        state
+    }
+
+    fn order(state: &QueueState) -> Vec<&str> {
+        state
+            .ordered()
+            .iter()
+            .map(|queued| queued.net_id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn missed_call_moves_back_past_the_policy() {
+        let dir = temp_dir();
+        let mut state = accommodation_course(&dir, &["a", "b", "c", "d"]);
+        assert_eq!(order(&state), ["a", "b", "c", "d"]);
+        state.apply(&entry(
+            10,
+            10,
+            Event::Called {
+                net_id: "a".to_owned(),
+                staff: "ta1".to_owned(),
+            },
+        ));
+        state.apply(&entry(
+            11,
+            13,
+            Event::MissedCall {
+                net_id: "a".to_owned(),
+                places: 2,
+                behind: vec!["b".to_owned(), "c".to_owned()],
+            },
+        ));
+        assert_eq!(order(&state), ["b", "c", "a", "d"]);
+
+        // Once those they were moved behind are gone, the policy decides
+        // again.
+        for (seq, net_id) in [(12, "b"), (13, "c")] {
+            state.apply(&entry(
+                seq,
+                14,
+                Event::Left {
+                    net_id: net_id.to_owned(),
+                },
+            ));
+        }
+        assert_eq!(order(&state), ["a", "d"]);
+    }
+
+    #[test]
+    fn requeued_student_goes_first_past_the_policy() {
+        let dir = temp_dir();
+        let mut state = accommodation_course(&dir, &["a", "b"]);
+        state.apply(&entry(
+            10,
+            10,
+            Event::Claimed {
+                net_id: "b".to_owned(),
+                staff: "ta1".to_owned(),
+            },
+        ));
+        state.apply(&entry(
+            11,
+            12,
+            Event::Requeued {
+                staff: "ta1".to_owned(),
+            },
+        ));
+        assert_eq!(order(&state), ["b", "a"]);
+    }
+}
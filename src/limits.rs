@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Local, Utc};
+use serde::Deserialize;
+
+use crate::{start_of_today, CommandResult, Question, QueueState};
+
+/// What a course's visit limit counts over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VisitWindow {
+    /// Since midnight.
+    #[default]
+    Day,
+    /// Since the scheduled session started, or the queue was last closed
+    /// for courses without a schedule.
+    Session,
+}
+
+impl QueueState {
+    /// When the current visit limit window started.
+    fn window_start(&self) -> DateTime<Utc> {
+        match self.config.max_visits_per {
+            VisitWindow::Day => start_of_today(),
+            VisitWindow::Session => match &self.session {
+                Some(session) if !session.closed => session.start,
+                _ => self.closed_at.unwrap_or_else(start_of_today),
+            },
+        }
+    }
+
+    /// Checks the course's visit limit and cooldown for `net_id` at `now`.
+    /// The error says when they may join again.
+    pub fn check_limits(&self, net_id: &str, now: DateTime<Utc>) -> CommandResult {
+        let Some(student) = self.students.get(net_id) else {
+            return Ok(());
+        };
+        let helped = student
+            .queue_times
+            .iter()
+            .filter(|visit| visit.was_helped())
+            .collect::<Vec<_>>();
+
+        if let Some(max_visits) = self.config.max_visits {
+            let start = self.window_start();
+            let visits = helped.iter().filter(|visit| visit.at >= start).count();
+            if visits >= max_visits {
+                let (window, rejoin) = match self.config.max_visits_per {
+                    VisitWindow::Day => ("today", "tomorrow"),
+                    VisitWindow::Session => ("this session", "next session"),
+                };
+                return Err(format!(
+                    "You've been helped {} times {}, the most allowed. You may rejoin {}.",
+                    visits, window, rejoin
+                ));
+            }
+        }
+
+        let cooldown = Duration::from_secs(self.config.cooldown_minutes * 60);
+        if let Some(last) = helped.iter().map(|visit| visit.at).max() {
+            let rejoin = last + cooldown;
+            if now < rejoin {
+                return Err(format!(
+                    "You were helped at {}, you may rejoin at {}.",
+                    last.with_timezone(&Local).format("%H:%M"),
+                    rejoin.with_timezone(&Local).format("%H:%M")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that staff can admit a student, printing the limits they
+    /// would override.
+    pub fn check_admit(&self, net_id: &str) -> CommandResult {
+        self.check_queueable(net_id)?;
+        if let Err(err) = self.check_limits(net_id, Utc::now()) {
+            println!("Overriding: {}", err);
+        }
+        Ok(())
+    }
+
+    /// Adds a student past the visit limit and cooldown, for when staff
+    /// make an exception.
+    ///
+    /// `admit <netid>`
+    pub fn admit(&mut self, net_id: &str, question: Question, staff: &str) -> CommandResult {
+        self.check_queueable(net_id)?;
+        self.add_unchecked(net_id, question, Some(staff))
+    }
+}
@@ -1,70 +1,429 @@
+mod calls;
+mod capacity;
+mod commands;
+mod config;
+mod credentials;
+mod dashboard;
+mod display;
+mod estimate;
+mod events;
+mod fsck;
+mod http;
+mod journal;
+mod limits;
+mod ordering;
+mod persist;
+mod roster;
+mod schedule;
+mod topics;
+mod verify;
+mod web;
+
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
-    fs::{File, OpenOptions},
-    io::{BufRead, BufReader, Write},
+    collections::{BTreeMap, HashMap, VecDeque},
+    fs::File,
+    io::Write,
+    path::Path,
     process::exit,
-    time::{Duration, Instant},
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
-use chrono::{DateTime, Local};
-use rpassword::read_password;
+use capacity::LockReason;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use config::{Config, CourseConfig, DEFAULT_CONFIG_PATH};
+use credentials::{prompt_new_password, Credentials, Login, DEFAULT_CREDENTIALS_PATH};
+use estimate::format_estimate;
+use events::Subscribers;
+use journal::Event;
+use roster::{PendingRoster, RosterDiff, RosterFormatConfig};
+use schedule::Session;
 use serde::{Deserialize, Deserializer, Serialize};
-
-use serde::ser::{SerializeStruct, Serializer};
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+use verify::{FailedAttempts, Pins, Verification};
 
 type CommandResult = Result<(), String>;
 
-#[derive(Debug, Clone, Copy)]
-struct SerializableInstant(Instant);
-impl SerializableInstant {
-    fn now() -> Self {
-        Self(Instant::now())
+/// Reads an entry time, accepting the `0` that old backups stored in place
+/// of a real timestamp.
+fn deserialize_entry_time<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum EntryTime {
+        Timestamp(DateTime<Utc>),
+        /// Backups written before entry times were recorded. The real time
+        /// is lost, so the student is treated as having just joined.
+        Legacy(#[allow(dead_code)] u32),
+    }
+
+    Ok(match EntryTime::deserialize(deserializer)? {
+        EntryTime::Timestamp(time) => time,
+        EntryTime::Legacy(_) => Utc::now(),
+    })
+}
+
+/// Parses a time typed at the terminal: RFC 3339, or a local
+/// "YYYY-MM-DD HH:MM[:SS]", or a local "HH:MM[:SS]" today.
+fn parse_time(input: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+        .or_else(|| {
+            ["%H:%M:%S", "%H:%M"]
+                .iter()
+                .find_map(|format| NaiveTime::parse_from_str(input, format).ok())
+                .map(|time| Local::now().date_naive().and_time(time))
+        })
+        .or_else(|| {
+            NaiveDate::parse_from_str(input, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_time(NaiveTime::MIN))
+        });
+
+    match naive.and_then(|naive| Local.from_local_datetime(&naive).earliest()) {
+        Some(time) => Ok(time.with_timezone(&Utc)),
+        None => Err(format!("Invalid time \"{}\".", input)),
     }
-    fn elapsed(&self) -> Duration {
-        self.0.elapsed()
+}
+
+/// Asks a yes or no question at the terminal. Anything but yes is a no.
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N]:", question);
+    std::io::stdout().flush().unwrap();
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
     }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
 }
-impl Serialize for SerializableInstant {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        serializer.serialize_u32(0)
+
+/// First name and last initial, what's shown of a student in public.
+/// "anna smith" becomes "Anna S."
+fn short_name(student: &Student) -> String {
+    let mut first_chars = student.first.chars();
+    let first = match first_chars.next() {
+        Some(c) => c.to_uppercase().chain(first_chars).collect::<String>(),
+        None => String::new(),
+    };
+    match student.last.chars().next() {
+        Some(initial) => format!("{} {}.", first, initial.to_uppercase()),
+        None => first,
     }
 }
-impl<'de> Deserialize<'de> for SerializableInstant {
-    fn deserialize<D>(deserializer: D) -> Result<SerializableInstant, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        i32::deserialize(deserializer)?;
-        Ok(SerializableInstant(Instant::now()))
+
+/// Start of the current day in local time.
+fn start_of_today() -> DateTime<Utc> {
+    let midnight = Local::now().date_naive().and_time(NaiveTime::MIN);
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(Utc::now)
+}
+
+/// Formats a duration for people, e.g. "1h 05m 12s".
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}m {:02}s", secs / 60, secs % 60)
     }
 }
 
+/// Mean of the durations, `None` if there are none.
+fn average(durations: impl Iterator<Item = Duration>) -> Option<Duration> {
+    let (total, count) = durations.fold((Duration::ZERO, 0u32), |(total, count), duration| {
+        (total + duration, count + 1)
+    });
+    (count > 0).then(|| total / count)
+}
+
+/// How a student's time in the queue ended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    /// Popped, or claimed and then done.
+    #[default]
+    Helped,
+    /// Took themselves out of the queue before being helped.
+    Left,
+    /// Dropped from the queue after missing too many calls.
+    NoShow,
+}
+
+/// One time a student was taken off the queue.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Visit {
+    /// Time spent in the queue.
+    pub wait: Duration,
+    /// When they were popped.
+    pub at: DateTime<Utc>,
+    /// Netid of the staff member who popped them. Unknown for visits
+    /// recorded before pops were attributed.
+    #[serde(default)]
+    pub staff: Option<String>,
+    /// How long the help itself took, for students who were claimed and
+    /// then marked done. Popped students have none.
+    #[serde(default)]
+    pub help: Option<Duration>,
+    /// Visits from before students could leave were all helped.
+    #[serde(default)]
+    pub outcome: Outcome,
+    /// How long of the wait they had stepped away for.
+    #[serde(default, skip_serializing_if = "Duration::is_zero")]
+    pub away: Duration,
+    /// What they came for.
+    #[serde(default, skip_serializing_if = "Question::is_empty")]
+    pub question: Question,
+    /// Id of the scheduled session the student joined in, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+}
+impl Visit {
+    pub fn was_helped(&self) -> bool {
+        self.outcome == Outcome::Helped
+    }
+}
+
+/// Reads a student's visits, accepting the `(wait, "dd/mm/YYYY HH:MM")`
+/// pairs that old backups stored.
+fn deserialize_visits<'de, D>(deserializer: D) -> Result<Vec<Visit>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum VisitRepr {
+        Current(Visit),
+        Legacy(Duration, String),
+    }
+
+    Ok(Vec::<VisitRepr>::deserialize(deserializer)?
+        .into_iter()
+        .map(|visit| match visit {
+            VisitRepr::Current(visit) => visit,
+            VisitRepr::Legacy(wait, at) => Visit {
+                wait,
+                at: NaiveDateTime::parse_from_str(&at, "%d/%m/%Y %H:%M")
+                    .ok()
+                    .and_then(|at| Local.from_local_datetime(&at).earliest())
+                    .map(|at| at.with_timezone(&Utc))
+                    .unwrap_or_default(),
+                staff: None,
+                help: None,
+                outcome: Outcome::Helped,
+                away: Duration::ZERO,
+                question: Question::default(),
+                session: None,
+            },
+        })
+        .collect())
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Student {
     pub first: String,
     pub last: String,
-    /// When popped, time spent in the queue is put here.
-    pub queue_times: Vec<(Duration, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+    /// University student ID number, not the netid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub student_id: Option<String>,
+    /// No longer on the roster. Kept around for their history, but they
+    /// can't join the queue.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dropped: bool,
+    /// Goes first when the course orders by accommodation.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub accommodation: bool,
+    /// When popped, the visit is recorded here.
+    #[serde(deserialize_with = "deserialize_visits")]
+    pub queue_times: Vec<Visit>,
+    /// When the student was called and didn't show up.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missed_calls: Vec<DateTime<Utc>>,
 }
 
+/// Time between a staff member's check-in and check-out.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+struct Shift {
+    pub start: DateTime<Utc>,
+    /// `None` while they are still on duty.
+    pub end: Option<DateTime<Utc>>,
+}
+impl Shift {
+    /// How much of the shift falls between `from` and `to`. Open shifts
+    /// count up to `now`.
+    pub fn overlap(&self, from: DateTime<Utc>, to: DateTime<Utc>, now: DateTime<Utc>) -> Duration {
+        let start = self.start.max(from);
+        let end = self.end.unwrap_or(now).min(to);
+        (end - start).to_std().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "StaffMemberRepr")]
 struct StaffMember {
-    /// Checkin times.
-    pub checkin_times: Vec<String>,
+    /// Every shift, oldest first. Only the last one can still be open.
+    pub shifts: Vec<Shift>,
+}
+impl StaffMember {
+    pub fn on_duty_since(&self) -> Option<DateTime<Utc>> {
+        self.shifts
+            .last()
+            .filter(|shift| shift.end.is_none())
+            .map(|shift| shift.start)
+    }
+    pub fn is_on_duty(&self) -> bool {
+        self.on_duty_since().is_some()
+    }
+    /// Closes the open shift, if there is one.
+    pub fn check_out(&mut self, at: DateTime<Utc>) {
+        if let Some(shift) = self.shifts.last_mut().filter(|shift| shift.end.is_none()) {
+            shift.end = Some(at);
+        }
+    }
+}
+
+/// [`StaffMember`] as stored, including the check-in times kept before
+/// check-outs existed.
+#[derive(Deserialize)]
+struct StaffMemberRepr {
+    #[serde(default)]
+    shifts: Vec<Shift>,
+    /// "dd/mm/YYYY HH:MM" check-in times. How long those shifts lasted was
+    /// never recorded, so they become zero length shifts.
+    #[serde(default)]
+    checkin_times: Vec<String>,
+}
+impl From<StaffMemberRepr> for StaffMember {
+    fn from(repr: StaffMemberRepr) -> Self {
+        let mut shifts = repr
+            .checkin_times
+            .iter()
+            .filter_map(|time| NaiveDateTime::parse_from_str(time, "%d/%m/%Y %H:%M").ok())
+            .filter_map(|time| Local.from_local_datetime(&time).earliest())
+            .map(|time| Shift {
+                start: time.with_timezone(&Utc),
+                end: Some(time.with_timezone(&Utc)),
+            })
+            .collect::<Vec<Shift>>();
+        shifts.extend(repr.shifts);
+        Self { shifts }
+    }
+}
+
+/// What a student wants help with, given when they join.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Question {
+    /// One of the course's topics.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    /// Anything else they want staff to know, in their own words.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+impl Question {
+    pub fn is_empty(&self) -> bool {
+        self.topic.is_none() && self.notes.is_none()
+    }
+}
+/// "[HW3] notes", for staff.
+impl std::fmt::Display for Question {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.topic, &self.notes) {
+            (Some(topic), Some(notes)) => write!(f, "[{}] {}", topic, notes),
+            (Some(topic), None) => write!(f, "[{}]", topic),
+            (None, Some(notes)) => write!(f, "{}", notes),
+            (None, None) => Ok(()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct QueuedStudent {
     /// Time the student entered into the queue.
-    pub entry_time: SerializableInstant,
+    #[serde(deserialize_with = "deserialize_entry_time")]
+    pub entry_time: DateTime<Utc>,
     /// Key into the students [`HashMap`]
     pub net_id: String,
+    /// When the student stepped away, holding their spot. Staff skip them
+    /// until they are back.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub away_since: Option<DateTime<Utc>>,
+    /// Time spent stepped away before, not counting the current time.
+    #[serde(default, skip_serializing_if = "Duration::is_zero")]
+    pub away: Duration,
+    /// Set while a staff member has called the student and waits for them
+    /// to show up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub called: Option<Call>,
+    /// Calls missed since joining.
+    #[serde(default)]
+    pub missed: u32,
+    #[serde(default, skip_serializing_if = "Question::is_empty")]
+    pub question: Question,
+    /// Id of the scheduled session they joined in, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    /// The students they have to wait for after missing a call, see
+    /// [`QueueState::ordered`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub behind: Vec<String>,
+    /// Put back by staff who couldn't finish helping them, so they go first.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub requeued: bool,
+}
+impl QueuedStudent {
+    /// How long the student had been waiting at `now`.
+    pub fn time_in_queue(&self, now: DateTime<Utc>) -> Duration {
+        (now - self.entry_time).to_std().unwrap_or_default()
+    }
+    pub fn is_away(&self) -> bool {
+        self.away_since.is_some()
+    }
+    /// All the time they have been stepped away, up to `now`.
+    pub fn time_away(&self, now: DateTime<Utc>) -> Duration {
+        let current = self
+            .away_since
+            .map(|since| (now - since).to_std().unwrap_or_default())
+            .unwrap_or_default();
+        self.away + current
+    }
+}
+
+/// A staff member calling a student, who has a few minutes to show up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Call {
+    /// Netid of the staff member who called them.
+    pub staff: String,
+    pub at: DateTime<Utc>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+/// A student taken off the queue who is being helped right now.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HelpSession {
+    pub student: QueuedStudent,
+    /// Netid of the staff member helping them.
+    pub staff: String,
+    /// When the staff member claimed them.
+    pub claimed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct QueueState {
     /// Roster of all students.
     pub students: HashMap<String, Student>,
@@ -72,196 +431,610 @@ struct QueueState {
     pub staff: HashMap<String, StaffMember>,
     /// The actual queue.
     pub queue: VecDeque<QueuedStudent>,
+    /// Students being helped, at most one per staff member.
+    #[serde(default)]
+    pub helping: Vec<HelpSession>,
     /// Whether the queue is locked.
     pub locked: bool,
-    /// Password for staff actions.
-    pub password: String,
+    /// Why the queue locked by itself, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock_reason: Option<LockReason>,
+    /// When the queue was last closed with `close`, where the current
+    /// session started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed_at: Option<DateTime<Utc>>,
+    /// The session the schedule last opened the queue for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<Session>,
+    /// Sequence number of the last journal entry applied to this state.
+    #[serde(default)]
+    pub journal_seq: u64,
 
-    pub backup_file: String,
+    /// Course settings from the config file. Never persisted, the config
+    /// file stays the source of truth.
+    #[serde(skip)]
+    pub config: CourseConfig,
+    /// Told about every change.
+    #[serde(skip)]
+    pub subscribers: Subscribers,
+    /// Students' PINs, kept in their own file.
+    #[serde(skip)]
+    pub pins: Pins,
+    /// Wrong proofs from students, to lock out guessing.
+    #[serde(skip)]
+    pub failed_attempts: FailedAttempts,
 }
 impl QueueState {
-    pub fn new(password: String, backup_file: String) -> Self {
+    pub fn new(config: CourseConfig) -> Self {
         Self {
             students: HashMap::new(),
             staff: HashMap::new(),
             queue: VecDeque::new(),
+            helping: Vec::new(),
             locked: false,
-            password,
-            backup_file,
+            lock_reason: None,
+            closed_at: None,
+            session: None,
+            journal_seq: 0,
+            config,
+            subscribers: Subscribers::default(),
+            pins: Pins::default(),
+            failed_attempts: FailedAttempts::default(),
         }
     }
 
-    fn authenticate(&self) -> CommandResult {
-        print!("Enter password:");
-        std::io::stdout().flush().unwrap();
-        let password = read_password().unwrap();
-        if password == self.password {
-            Ok(())
-        } else {
-            Err("Invalid password.".to_owned())
-        }
+    /// Replaces the state with one read from disk, keeping the course
+    /// config, subscribers and PINs. The journal position comes along with
+    /// the new state.
+    fn restore(&mut self, mut new_self: QueueState) {
+        new_self.config = std::mem::take(&mut self.config);
+        new_self.subscribers = std::mem::take(&mut self.subscribers);
+        new_self.pins = std::mem::take(&mut self.pins);
+        new_self.failed_attempts = std::mem::take(&mut self.failed_attempts);
+        *self = new_self;
     }
 
     pub fn save_backup(&self) {
-        let Ok(mut file) = File::create(&self.backup_file) else {
-            println!("Invalid file.");
-            return;
-        };
-
         let Ok(output) = serde_json::to_string(self) else {
             println!("Failed to serialize.");
             return;
         };
 
-        let Ok(_) = file.write_all(output.as_bytes()) else {
-            println!("Failed to write bytes.");
+        let path = Path::new(&self.config.backup_file);
+        if let Err(err) = persist::write_atomic(path, output.as_bytes()) {
+            println!("Failed to write backup file: {}", err);
             return;
-        };
+        }
+
+        if let Err(err) = self.config.snapshots().take(output.as_bytes()) {
+            println!("Failed to write snapshot: {}", err);
+        }
     }
 
     pub fn load_backup(&mut self) {
-        let Ok(contents) = std::fs::read_to_string(&self.backup_file) else {
-            println!("Backup file does not exist.");
-            return;
-        };
-
-        let Ok(new_self) = serde_json::from_str(&contents) else {
-            println!("Failed to parse backup file.");
-            return;
-        };
+        match std::fs::read_to_string(&self.config.backup_file) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(new_self) => {
+                    self.restore(new_self);
+                    println!("Loaded {} from backup.", self.config.id);
+                    return;
+                }
+                Err(_) => println!("Failed to parse backup file."),
+            },
+            Err(_) => println!("Backup file does not exist."),
+        }
 
-        *self = new_self;
+        // The backup is missing or corrupt, fall back to the newest snapshot
+        // that still parses.
+        for snapshot in self.config.snapshots().newest_first() {
+            let Ok(contents) = std::fs::read_to_string(&snapshot) else {
+                continue;
+            };
+            let Ok(new_self) = serde_json::from_str(&contents) else {
+                println!("Skipping corrupt snapshot {}.", snapshot.display());
+                continue;
+            };
 
-        println!("Loaded from backup.");
+            self.restore(new_self);
+            println!(
+                "Loaded {} from snapshot {}.",
+                self.config.id,
+                snapshot.display()
+            );
+            // Put a valid backup file back in place.
+            self.save_backup();
+            return;
+        }
     }
 
     /// Staff log in.
     ///
-    /// `checkin <netid>`
-    pub fn checkin(&mut self, parts: &[String]) -> CommandResult {
-        if parts.len() < 2 {
-            return Err("Usage: \"checkin <netid>\".".to_owned());
-        }
-
-        self.authenticate()?;
-
-        let Some(staff_member) = self.staff.get_mut(&parts[1]) else {
+    /// `checkin`
+    pub fn checkin(&mut self, net_id: &str) -> CommandResult {
+        let Some(staff_member) = self.staff.get(net_id) else {
             return Err("Not a member of staff. Message James on slack.".to_owned());
         };
+        if staff_member.is_on_duty() {
+            return Err(format!("{} is already checked in.", net_id));
+        }
 
-        staff_member.checkin_times.push(format!(
-            "{}",
-            chrono::offset::Local::now().format("%d/%m/%Y %H:%M")
-        ));
-
-        self.save_backup();
+        self.record(Event::CheckedIn {
+            net_id: net_id.to_owned(),
+        })?;
 
-        println!("{} checked in.", parts[1]);
+        println!("{} checked in.", net_id);
         Ok(())
     }
-    /// Add a name to the queue.
+    /// Staff log out.
     ///
-    /// `add <netid>`
-    pub fn add(&mut self, parts: &[String]) -> CommandResult {
-        if parts.len() < 2 {
-            return Err("Usage: \"add <netid>\".".to_owned());
+    /// `checkout`
+    pub fn checkout(&mut self, net_id: &str) -> CommandResult {
+        if !self
+            .staff
+            .get(net_id)
+            .is_some_and(|staff_member| staff_member.is_on_duty())
+        {
+            return Err(format!("{} isn't checked in.", net_id));
+        }
+        if self.helping.iter().any(|session| session.staff == net_id) {
+            return Err(
+                "Finish helping your student first, with \"done\" or \"requeue\".".to_owned(),
+            );
+        }
+
+        self.record(Event::CheckedOut {
+            net_id: net_id.to_owned(),
+        })?;
+
+        println!("{} checked out.", net_id);
+        Ok(())
+    }
+    /// Checks out everyone still on duty, when the program quits.
+    pub fn checkout_all(&mut self) {
+        let mut on_duty = self
+            .staff
+            .iter()
+            .filter(|(_, staff_member)| staff_member.is_on_duty())
+            .map(|(net_id, _)| net_id.clone())
+            .collect::<Vec<String>>();
+        on_duty.sort();
+        for net_id in on_duty {
+            if let Err(err) = self.record(Event::CheckedOut { net_id }) {
+                println!("{}", err);
+            }
+        }
+    }
+    /// Checks out the staff a previous run left on duty, because it crashed
+    /// or was killed, as of its last change. Otherwise their shifts would
+    /// count as worked up to now.
+    pub fn close_stale_shifts(&mut self) {
+        if !self.staff.values().any(StaffMember::is_on_duty) {
+            return;
+        }
+        let Some(end) = self.last_change() else {
+            return;
+        };
+        match self.record(Event::ShiftsClosed { end }) {
+            Ok(()) => println!(
+                "Checked out the staff left on duty in {} as of {}.",
+                self.config.id,
+                end.with_timezone(&Local).format("%d/%m/%Y %H:%M")
+            ),
+            Err(err) => println!("{}", err),
         }
+    }
+    /// Locks the queue and checks out everyone still on duty.
+    ///
+    /// `close`
+    pub fn close(&mut self) -> CommandResult {
+        self.record(Event::Closed)?;
+        println!("Queue is closed, all staff checked out.");
+        Ok(())
+    }
+    /// Add a name to the queue, with what they need help with. Who they are
+    /// has to be checked beforehand, see [`QueueState::verify_student`].
+    pub fn add(&mut self, net_id: &str, question: Question) -> CommandResult {
+        self.check_join(net_id)?;
+        self.add_unchecked(net_id, question, None)
+    }
+    /// Adds a student who may join, skipping the visit limits if a staff
+    /// member admitted them.
+    fn add_unchecked(
+        &mut self,
+        net_id: &str,
+        question: Question,
+        admitted_by: Option<&str>,
+    ) -> CommandResult {
+        let question = self.check_question(question)?;
+
+        self.record(Event::Added {
+            net_id: net_id.to_owned(),
+            question,
+            admitted_by: admitted_by.map(str::to_owned),
+        })?;
 
-        if !self.students.contains_key(&parts[1]) {
+        println!(
+            "Added to queue in position {}",
+            self.position_of(net_id).unwrap() + 1
+        );
+        Ok(())
+    }
+    /// Whether `net_id` may join the queue right now, visit limits
+    /// included.
+    pub fn check_join(&self, net_id: &str) -> CommandResult {
+        self.check_queueable(net_id)?;
+        self.check_limits(net_id, Utc::now())
+    }
+    /// Whether `net_id` could be in the queue at all: on the roster, not
+    /// already queued or being helped, and the queue open.
+    fn check_queueable(&self, net_id: &str) -> CommandResult {
+        if self
+            .students
+            .get(net_id)
+            .is_none_or(|student| student.dropped)
+        {
             return Err(
                 "Not a student. Contact course staff if you believe this is a mistake.".to_owned(),
             );
         };
 
         if self.locked {
-            return Err("Queue is locked.".to_owned());
+            return Err(match &self.lock_reason {
+                Some(reason) => format!("Queue is locked. {}", reason),
+                None => "Queue is locked.".to_owned(),
+            });
         }
 
-        if let Some((i, _)) = self
-            .queue
+        if let Some(i) = self.position_of(net_id) {
+            return Err(format!("Already in the queue, position: {}", i));
+        }
+
+        if self
+            .helping
             .iter()
-            .enumerate()
-            .find(|(_, entry)| entry.net_id == parts[1])
+            .any(|session| session.student.net_id == net_id)
         {
-            return Err(format!("Already in the queue, position: {}", i));
+            return Err("Already being helped.".to_owned());
         }
+        Ok(())
+    }
+    pub fn ensure_queued(&self, net_id: &str) -> Result<&QueuedStudent, String> {
+        match self.queue.iter().find(|queued| queued.net_id == net_id) {
+            Some(queued) => Ok(queued),
+            None => Err(format!("{} is not in the queue.", net_id)),
+        }
+    }
+    /// Takes a student out of the queue, e.g. when they give up waiting.
+    /// Recorded in their history as having left, not as a visit.
+    ///
+    /// `leave <netid>`
+    pub fn leave(&mut self, net_id: &str) -> CommandResult {
+        self.ensure_queued(net_id)?;
 
-        self.queue.push_back(QueuedStudent {
-            entry_time: SerializableInstant::now(),
-            net_id: parts[1].clone(),
-        });
+        self.record(Event::Left {
+            net_id: net_id.to_owned(),
+        })?;
 
-        println!("Added to queue in position {}", self.queue.len());
-        self.save_backup();
+        println!("{} left the queue.", net_id);
         Ok(())
     }
-    /// Remove someone from the queue. Optionally record who popped them.
+    /// Holds a student's spot while they step away. Staff skip them until
+    /// they are back.
     ///
-    /// `pop`
-    pub fn pop(&mut self) -> CommandResult {
-        self.authenticate()?;
+    /// `away <netid>`
+    pub fn step_away(&mut self, net_id: &str) -> CommandResult {
+        let queued = self.ensure_queued(net_id)?;
+        if queued.is_away() {
+            return Err(format!("{} already stepped away.", net_id));
+        }
+        if let Some(call) = &queued.called {
+            return Err(format!("{} was called by {}.", net_id, call.staff));
+        }
+
+        self.record(Event::SteppedAway {
+            net_id: net_id.to_owned(),
+        })?;
+
+        println!("{} stepped away, their spot is held.", net_id);
+        Ok(())
+    }
+    /// Puts a student who stepped away back in line, in the spot they held.
+    ///
+    /// `back <netid>`
+    pub fn come_back(&mut self, net_id: &str) -> CommandResult {
+        if !self.ensure_queued(net_id)?.is_away() {
+            return Err(format!("{} didn't step away.", net_id));
+        }
+
+        self.record(Event::Returned {
+            net_id: net_id.to_owned(),
+        })?;
+
+        println!(
+            "Welcome back, {} is #{} in line.",
+            net_id,
+            self.position_of(net_id).unwrap() + 1
+        );
+        Ok(())
+    }
+    /// Prints where a student is in line and roughly how long they have
+    /// left to wait.
+    ///
+    /// `status <netid>`
+    pub fn status(&mut self, net_id: &str) -> CommandResult {
+        if let Some(session) = self
+            .helping
+            .iter()
+            .find(|session| session.student.net_id == net_id)
+        {
+            println!("{} is being helped by {}.", net_id, session.staff);
+            return Ok(());
+        }
 
-        let Some(student) = self.queue.pop_front() else {
+        let queued = self.ensure_queued(net_id)?;
+        let position = self.position_of(net_id).unwrap();
+        println!(
+            "{} is #{} in line, waiting for {}.",
+            net_id,
+            position + 1,
+            format_duration(queued.time_in_queue(Utc::now()))
+        );
+        if let Some(call) = &queued.called {
+            println!(
+                "Called by {}, confirm with \"here\" within {}.",
+                call.staff,
+                format_duration(self.grace_left(call, Utc::now()))
+            );
+        } else if queued.is_away() {
+            println!("Stepped away, their spot is held until they are back.");
+        } else {
+            match self.estimated_wait(position) {
+                Some(wait) => println!("Estimated wait: {}.", format_estimate(wait)),
+                None => println!("Estimated wait: unknown, no staff on duty."),
+            }
+        }
+        Ok(())
+    }
+    fn ensure_checked_in(&self, staff: &str) -> CommandResult {
+        if !self
+            .staff
+            .get(staff)
+            .is_some_and(|staff_member| staff_member.is_on_duty())
+        {
+            return Err(format!("{} has to check in first.", staff));
+        }
+        Ok(())
+    }
+    /// The student staff take next: by the course's ordering, the first
+    /// one who hasn't stepped away and isn't called by someone else already.
+    pub fn next_up(&self) -> Result<&QueuedStudent, String> {
+        if self.queue.is_empty() {
             return Err("Queue is empty.".to_owned());
+        }
+        match self
+            .ordered()
+            .into_iter()
+            .find(|queued| !queued.is_away() && queued.called.is_none())
+        {
+            Some(queued) => Ok(queued),
+            None => Err("Everyone in the queue stepped away or was called.".to_owned()),
+        }
+    }
+    /// Remove someone from the queue, recording which staff member took them.
+    /// Students who stepped away are skipped.
+    ///
+    /// `pop`
+    pub fn pop(&mut self, staff: &str) -> CommandResult {
+        self.ensure_checked_in(staff)?;
+
+        let student = self.next_up()?;
+        let net_id = student.net_id.clone();
+        let time_in_queue = student.time_in_queue(Utc::now());
+
+        self.record(Event::Popped {
+            net_id: net_id.clone(),
+            staff: Some(staff.to_owned()),
+        })?;
+
+        println!(
+            "Popped: \"{}\" after {} in queue.",
+            self.student_name(&net_id),
+            format_duration(time_in_queue)
+        );
+
+        Ok(())
+    }
+    /// Takes the next student and marks them as being helped by `staff`,
+    /// until `done` or `requeue`. If `staff` called someone, that is who
+    /// they take, whether or not they confirmed.
+    ///
+    /// `claim`
+    pub fn claim(&mut self, staff: &str) -> CommandResult {
+        self.ensure_checked_in(staff)?;
+
+        if let Some(session) = self.helping.iter().find(|session| session.staff == staff) {
+            return Err(format!(
+                "Already helping {}, use \"done\" or \"requeue\" first.",
+                session.student.net_id
+            ));
+        }
+
+        let net_id = match self.called_by(staff) {
+            Some(queued) => queued.net_id.clone(),
+            None => self.next_up()?.net_id.clone(),
         };
-        let time_in_queue = student.entry_time.elapsed();
 
-        let student = self.students.get_mut(&student.net_id).unwrap();
-        student.queue_times.push((
-            time_in_queue,
-            format!("{}", chrono::offset::Local::now().format("%d/%m/%Y %H:%M")),
-        ));
+        self.record(Event::Claimed {
+            net_id: net_id.clone(),
+            staff: staff.to_owned(),
+        })?;
 
         println!(
-            "Popped: \"{} {}\" after {:?} in queue.",
-            student.first, student.last, time_in_queue
+            "{} is now helping \"{}\".",
+            staff,
+            self.student_name(&net_id)
         );
+        Ok(())
+    }
+    fn session_of(&self, staff: &str) -> Result<&HelpSession, String> {
+        match self.helping.iter().find(|session| session.staff == staff) {
+            Some(session) => Ok(session),
+            None => Err(format!("{} isn't helping anyone.", staff)),
+        }
+    }
+    /// Finishes helping the student `staff` claimed and records the visit.
+    ///
+    /// `done`
+    pub fn done(&mut self, staff: &str) -> CommandResult {
+        let session = self.session_of(staff)?;
+        let net_id = session.student.net_id.clone();
+        let now = Utc::now();
+        let wait = (session.claimed_at - session.student.entry_time)
+            .to_std()
+            .unwrap_or_default();
+        let help = (now - session.claimed_at).to_std().unwrap_or_default();
 
-        self.save_backup();
+        self.record(Event::Done {
+            staff: staff.to_owned(),
+        })?;
 
+        println!(
+            "Done with \"{}\" after {} of help, {} in queue.",
+            self.student_name(&net_id),
+            format_duration(help),
+            format_duration(wait)
+        );
+        Ok(())
+    }
+    /// Puts the student `staff` claimed back at the front of the queue.
+    ///
+    /// `requeue`
+    pub fn requeue(&mut self, staff: &str) -> CommandResult {
+        let net_id = self.session_of(staff)?.student.net_id.clone();
+
+        self.record(Event::Requeued {
+            staff: staff.to_owned(),
+        })?;
+
+        println!("{} is back at the front of the queue.", net_id);
         Ok(())
     }
     /// View's the queue.
     ///
     /// `view`
     pub fn view(&mut self) -> CommandResult {
+        self.print_queue(Utc::now());
+        Ok(())
+    }
+    /// Where `net_id` is in the queue by the course's ordering, 0 being
+    /// the front.
+    pub fn position_of(&self, net_id: &str) -> Option<usize> {
+        self.ordered()
+            .iter()
+            .position(|queued| queued.net_id == net_id)
+    }
+    /// A student's full name, or a placeholder if they aren't on the roster.
+    fn student_name(&self, net_id: &str) -> String {
+        match self.students.get(net_id) {
+            Some(student) => format!("{} {}", student.first, student.last),
+            None => format!("unknown student {}", net_id),
+        }
+    }
+    /// Prints the queue with wait times as of `now`.
+    fn print_queue(&self, now: DateTime<Utc>) {
+        if let Some(session) = self.session.as_ref().filter(|session| !session.closed) {
+            println!(
+                "Session {} until {}",
+                session.id,
+                session.end.with_timezone(&Local).format("%H:%M")
+            );
+        }
+        let mut on_duty = self
+            .staff
+            .iter()
+            .filter_map(|(net_id, staff_member)| Some((net_id, staff_member.on_duty_since()?)))
+            .collect::<Vec<(&String, DateTime<Utc>)>>();
+        on_duty.sort_by_key(|(_, since)| *since);
+        if !on_duty.is_empty() {
+            let on_duty = on_duty
+                .iter()
+                .map(|(net_id, since)| {
+                    format!(
+                        "{} (since {})",
+                        net_id,
+                        since.with_timezone(&Local).format("%H:%M")
+                    )
+                })
+                .collect::<Vec<String>>();
+            println!("On duty: {}", on_duty.join(", "));
+        }
+
+        for session in &self.helping {
+            let help_time = (now - session.claimed_at).to_std().unwrap_or_default();
+            println!(
+                "Being helped: {} by {} for {} {}",
+                self.student_name(&session.student.net_id),
+                session.staff,
+                format_duration(help_time),
+                session.student.question
+            );
+        }
+
         if self.queue.is_empty() {
             println!("Queue is empty.");
-            return Ok(());
+            return;
         }
         if self.locked {
-            println!("QUEUE IS LOCKED!");
+            match &self.lock_reason {
+                Some(reason) => println!("QUEUE IS LOCKED! {}", reason),
+                None => println!("QUEUE IS LOCKED!"),
+            }
         }
-        for (i, student) in self.queue.iter().enumerate() {
-            let time_in_queue = student.entry_time.elapsed();
-            let student = self.students.get(&student.net_id).unwrap();
+        for (i, student) in self.ordered().into_iter().enumerate() {
+            let mut note = match &student.called {
+                Some(call) => format!(" (called by {})", call.staff),
+                None if student.is_away() => " (stepped away)".to_owned(),
+                None => String::new(),
+            };
+            if !student.question.is_empty() {
+                note.push_str(&format!(" {}", student.question));
+            }
             println!(
-                "{}: {} {} for {:?}",
-                i, student.first, student.last, time_in_queue
+                "{}: {} for {}{}",
+                i,
+                self.student_name(&student.net_id),
+                format_duration(student.time_in_queue(now)),
+                note
             );
         }
-        Ok(())
     }
-    /// Clears the screen.
+    /// Shows the queue as it was at a past moment, rebuilt from the journal.
     ///
-    /// `clear`
-    pub fn clear(&mut self) -> CommandResult {
-        self.authenticate()?;
-        if clearscreen::clear().is_err() {
-            return Err("Failed to clear screen.".to_owned());
-        }
+    /// `replay [--until <time>]`
+    pub fn replay_until(&mut self, args: &[String]) -> CommandResult {
+        let until = match args.split_first() {
+            None => Utc::now(),
+            Some((flag, time)) if flag == "--until" && !time.is_empty() => {
+                parse_time(&time.join(" "))?
+            }
+            _ => return Err("Usage: \"replay [--until <time>]\".".to_owned()),
+        };
+
+        let state = self.replay(Some(until))?;
+        println!(
+            "{} as of {} (journal entry #{}):",
+            self.config.display_name(),
+            until.with_timezone(&Local).format("%d/%m/%Y %H:%M:%S"),
+            state.journal_seq
+        );
+        state.print_queue(until);
         Ok(())
     }
     /// Dumps the stats to a file.
     ///
     /// `stats <filename>`
-    pub fn stats(&mut self, parts: &[String]) -> CommandResult {
-        if parts.len() < 2 {
-            return Err("Usage: \"stats <filename>\".".to_owned());
-        }
-        self.authenticate()?;
-
-        let Ok(mut file) = File::create(&parts[1]) else {
+    pub fn stats(&mut self, filename: &str) -> CommandResult {
+        let Ok(mut file) = File::create(filename) else {
             return Err("Invalid file.".to_owned());
         };
 
@@ -280,50 +1053,38 @@ impl QueueState {
     ///
     /// `reset`
     pub fn reset(&mut self) -> CommandResult {
-        self.authenticate()?;
-        for student in self.students.values_mut() {
-            student.queue_times.clear();
-        }
-        self.queue.clear();
-        self.locked = false;
+        self.record(Event::Reset)?;
+        println!("Queue and stats reset.");
         Ok(())
     }
     /// Locks the queue.
     ///
     /// `lock`
     pub fn lock(&mut self) -> CommandResult {
-        self.authenticate()?;
-        self.locked = true;
+        self.record(Event::Locked)?;
         println!("Queue is locked.");
         Ok(())
     }
-    /// Unlocks the queue.
+    /// Unlocks the queue. A scheduled course only opens for its sessions,
+    /// extra hours go in its schedule.
     ///
     /// `unlock`
     pub fn unlock(&mut self) -> CommandResult {
-        self.authenticate()?;
-        self.locked = false;
+        if self.outside_hours() {
+            return Err(format!(
+                "{} is outside its office hours. Add extra hours to its schedule to open it.",
+                self.config.id
+            ));
+        }
+        self.record(Event::Unlocked)?;
         println!("Queue is unlocked.");
         Ok(())
     }
-    /// Exit the queue, saves the global state before doing so.
-    ///
-    /// `quit`
-    pub fn quit(&mut self) -> CommandResult {
-        self.authenticate()?;
-        self.save_backup();
-        exit(0);
-    }
     /// Load the global state from a file.
     ///
     /// `load <filename>`
-    pub fn load(&mut self, parts: &[String]) -> CommandResult {
-        if parts.len() < 2 {
-            return Err("Usage: \"save <filename>\".".to_owned());
-        }
-        self.authenticate()?;
-
-        let Ok(contents) = std::fs::read_to_string(&parts[1]) else {
+    pub fn load(&mut self, filename: &str) -> CommandResult {
+        let Ok(contents) = std::fs::read_to_string(filename) else {
             return Err("Invalid file.".to_owned());
         };
 
@@ -331,238 +1092,447 @@ impl QueueState {
             return Err("Failed to parse file.".to_owned());
         };
 
-        *self = new_self;
+        self.record(Event::StateLoaded {
+            state: Box::new(new_self),
+        })?;
 
         println!("Loaded from file.");
+        self.warn_problems();
 
         Ok(())
     }
-    /// Save the global state forcefully.
+    /// Prints how many students each staff member helped and how long those
+    /// students had waited on average.
     ///
-    /// `save <filename>`
-    pub fn save(&mut self, parts: &[String]) -> CommandResult {
-        if parts.len() < 2 {
-            return Err("Usage: \"save <filename>\".".to_owned());
+    /// `staff_report`
+    pub fn staff_report(&mut self) -> CommandResult {
+        let mut helped: BTreeMap<&str, Vec<&Visit>> = self
+            .staff
+            .keys()
+            .map(|net_id| (net_id.as_str(), Vec::new()))
+            .collect();
+        let mut unattributed = 0;
+        let mut left = 0;
+        let mut no_shows = 0;
+        for visit in self.students.values().flat_map(|s| s.queue_times.iter()) {
+            match visit.outcome {
+                Outcome::Helped => {}
+                Outcome::Left => left += 1,
+                Outcome::NoShow => no_shows += 1,
+            }
+            if !visit.was_helped() {
+                continue;
+            }
+            match &visit.staff {
+                Some(staff) => helped.entry(staff).or_default().push(visit),
+                None => unattributed += 1,
+            }
         }
-        self.authenticate()?;
 
-        let Ok(mut file) = File::create(&parts[1]) else {
-            return Err("Invalid file.".to_owned());
+        for (net_id, visits) in helped {
+            let average_wait = average(visits.iter().map(|visit| visit.wait));
+            let average_help = average(visits.iter().filter_map(|visit| visit.help));
+            println!(
+                "{}: helped {}, average wait {}, average help {}",
+                net_id,
+                visits.len(),
+                average_wait.map_or("-".to_owned(), format_duration),
+                average_help.map_or("-".to_owned(), format_duration)
+            );
+        }
+        if unattributed > 0 {
+            println!("{} visits from before pops were attributed.", unattributed);
+        }
+        if left > 0 {
+            println!("Left the queue before being helped: {}", left);
+        }
+        if no_shows > 0 {
+            println!("Dropped after missing their calls: {}", no_shows);
+        }
+        Ok(())
+    }
+    /// Prints the hours each staff member worked between two dates, both
+    /// inclusive, and optionally exports every shift as CSV.
+    ///
+    /// `hours <from> <to> [csv_file]`
+    pub fn hours(&mut self, from: &str, to: &str, csv_file: Option<&str>) -> CommandResult {
+        let parse_date = |date: &str| match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(date) => Ok(date),
+            Err(_) => Err(format!("Invalid date \"{}\", expected YYYY-MM-DD.", date)),
         };
+        let start_of = |date: NaiveDate| {
+            Local
+                .from_local_datetime(&date.and_time(NaiveTime::MIN))
+                .earliest()
+                .map(|time| time.with_timezone(&Utc))
+                .ok_or_else(|| format!("Invalid date \"{}\".", date))
+        };
+        let from = start_of(parse_date(from)?)?;
+        let to = start_of(parse_date(to)?.succ_opt().unwrap())?;
+        let now = Utc::now();
+
+        let mut net_ids = self.staff.keys().collect::<Vec<&String>>();
+        net_ids.sort();
 
+        let mut csv = String::from("netid,checkin,checkout,hours\n");
+        for net_id in net_ids {
+            let mut total = Duration::ZERO;
+            for shift in &self.staff[net_id].shifts {
+                let worked = shift.overlap(from, to, now);
+                if worked.is_zero() && shift.end.is_some() {
+                    continue;
+                }
+                total += worked;
+                csv.push_str(&format!(
+                    "{},{},{},{:.2}\n",
+                    net_id,
+                    shift.start.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                    shift
+                        .end
+                        .map(|end| end
+                            .with_timezone(&Local)
+                            .format("%Y-%m-%d %H:%M")
+                            .to_string())
+                        .unwrap_or_default(),
+                    worked.as_secs_f64() / 3600.0
+                ));
+            }
+            println!("{}: {:.2} hours", net_id, total.as_secs_f64() / 3600.0);
+        }
+
+        if let Some(csv_file) = csv_file {
+            if let Err(err) = persist::write_atomic(Path::new(csv_file), csv.as_bytes()) {
+                return Err(format!("Failed to write file: {}", err));
+            }
+            println!("Shifts saved to {}.", csv_file);
+        }
+        Ok(())
+    }
+    /// Save the global state forcefully.
+    ///
+    /// `save <filename>`
+    pub fn save(&mut self, filename: &str) -> CommandResult {
         let Ok(output) = serde_json::to_string(self) else {
             return Err("Failed to serialize.".to_owned());
         };
 
-        let Ok(_) = file.write_all(output.as_bytes()) else {
-            return Err("Failed to write bytes.".to_owned());
-        };
+        if let Err(err) = persist::write_atomic(Path::new(filename), output.as_bytes()) {
+            return Err(format!("Failed to write file: {}", err));
+        }
 
         println!("State saved.");
-        return Ok(());
+        Ok(())
     }
 
-    /// Add a staff member.
+    pub fn check_new_staff(&self, net_id: &str) -> CommandResult {
+        if self.staff.contains_key(net_id) {
+            return Err(format!("{} is already a staff member.", net_id));
+        }
+        Ok(())
+    }
+    /// Add a staff member. Their password, if they need one, is set
+    /// beforehand.
     ///
     /// `add_staff <netid>`
-    pub fn add_staff(&mut self, parts: &[String]) -> CommandResult {
-        if parts.len() < 2 {
-            return Err("Usage: \"add_staff <netid>\".".to_owned());
-        }
-        self.authenticate()?;
-        if self.staff.contains_key(&parts[1]) {
-            return Err(format!("{} is already a staff member.", parts[1]));
-        }
-        self.staff.insert(
-            parts[1].clone(),
-            StaffMember {
-                checkin_times: Vec::default(),
-            },
-        );
-        println!("Staff member {} added.", parts[1]);
-        self.save_backup();
+    pub fn add_staff(&mut self, net_id: &str) -> CommandResult {
+        let net_id = net_id.to_lowercase();
+        self.check_new_staff(&net_id)?;
+        self.record(Event::StaffAdded {
+            net_id: net_id.clone(),
+        })?;
+        println!("Staff member {} added.", net_id);
         Ok(())
     }
 
-    /// Load a roster. Overwrites the current one.
+    /// Reads a roster and shows what loading it would change. By default
+    /// it is merged into the current one: new students are added, missing
+    /// ones marked dropped and names updated, keeping everyone's history.
+    /// With `--replace` the current roster and its history are thrown away.
+    /// `None` if nothing would change.
     ///
-    /// `load_roster <path_to_file>`
-    pub fn load_roster(&mut self, parts: &[String]) -> CommandResult {
-        if parts.len() < 1 {
-            return Err("Usage: \"load_roster <path_to_file>\".".to_owned());
-        }
-        println!("parts: {:?}", parts);
-        self.authenticate()?;
-
-        let Ok(file) = OpenOptions::new().read(true).open(parts[1].clone()) else {
-            return Err("Invalid file1.".to_owned());
+    /// `load_roster <path_to_file> [format] [--replace]`
+    pub fn prepare_roster(&self, args: &[String]) -> Result<Option<PendingRoster>, String> {
+        let replace = args.iter().any(|arg| arg == "--replace");
+        let mut args = args.iter().filter(|arg| *arg != "--replace");
+        let Some(path) = args.next() else {
+            return Err("Usage: \"load_roster <path_to_file> [format] [--replace]\".".to_owned());
+        };
+        let format = match args.next() {
+            Some(name) => RosterFormatConfig::Preset(name.to_owned()).resolve()?,
+            None => self.config.roster_format.resolve()?,
         };
 
-        self.students.clear();
+        let Ok(file) = File::open(path) else {
+            return Err("Invalid file.".to_owned());
+        };
+        let parsed = roster::parse(file, &format)?;
 
-        for (i, line) in BufReader::new(file).lines().flatten().enumerate() {
-            let line_parts = line
-                .split(",")
-                .map(|s| s.to_owned())
-                .collect::<Vec<String>>();
+        for error in &parsed.errors {
+            println!("Line {}: {}", error.line, error.message);
+        }
+        if parsed.students.is_empty() {
+            return Err("No students found, roster not loaded.".to_owned());
+        }
 
-            if 3 != line_parts.len() {
-                return Err(format!("Err on line {}:{} ", i, line));
-            }
+        let students = parsed
+            .students
+            .into_iter()
+            .collect::<HashMap<String, Student>>();
+        let diff = RosterDiff::new(&self.students, &students);
+        diff.print(&self.students, &students);
+        if replace {
+            println!("Replacing the roster throws away every student's history.");
+        } else if diff.is_empty() {
+            println!("Roster unchanged.");
+            return Ok(None);
+        }
 
-            let netid = line_parts[2].to_lowercase();
-            if !self.students.contains_key(&netid) {
-                self.students.insert(
-                    netid,
-                    Student {
-                        first: line_parts[1].to_lowercase(),
-                        last: line_parts[0].to_lowercase(),
-                        queue_times: Vec::default(),
-                    },
-                );
-            }
+        Ok(Some(PendingRoster {
+            students,
+            replace,
+            skipped: parsed.errors.len(),
+        }))
+    }
+    /// Loads a roster read by [`QueueState::prepare_roster`], once staff
+    /// confirmed the changes.
+    pub fn load_roster(&mut self, roster: PendingRoster) -> CommandResult {
+        let students = roster.students;
+        if roster.replace {
+            self.record(Event::RosterLoaded { students })?;
+        } else {
+            self.record(Event::RosterMerged { students })?;
         }
 
-        println!("Imported {} students.", self.students.len());
-        self.save_backup();
+        println!(
+            "Roster loaded, {} students, skipped {} lines.",
+            self.students
+                .values()
+                .filter(|student| !student.dropped)
+                .count(),
+            roster.skipped
+        );
+        if self.config.verification == Verification::Pin {
+            self.issue_missing_pins()?;
+        }
+        self.warn_problems();
 
         Ok(())
     }
+}
 
-    // pub fn process_command(&mut self, command: &str) -> CommandResult {
-    //     let parts = command
-    //         .split_ascii_whitespace()
-    //         .map(|s| s.to_owned())
-    //         .collect::<Vec<String>>();
-    //     if parts.is_empty() {
-    //         return Err("Command is empty.".to_owned());
-    //     }
-
-    //     match parts[0].to_lowercase().as_str() {
-    //         "checkin" => self.checkin(&parts),
-    //         "add" => self.add(&parts),
-    //         "pop" => self.pop(),
-    //         "view" => self.view(),
-    //         "clear" => self.clear(),
-    //         "stats" => self.stats(&parts),
-    //         "reset" => self.reset(),
-    //         "lock" => self.lock(),
-    //         "unlock" => self.unlock(),
-    //         "help" => self.help(),
-    //         "quit" => self.quit(),
-    //         "load" => self.load(&parts),
-    //         "save" => self.save(&parts),
-    //         "add_staff" => self.add_staff(&parts),
-    //         "load_roster" => self.load_roster(&parts),
-    //         _ => Err("Unknown command.".to_owned()),
-    //     }
-    // }
-}
-
-#[derive(Serialize, Deserialize)]
 struct Queue {
-    ics53: QueueState,
-    ics51: QueueState,
+    /// Every course's queue, key'd by course id.
+    courses: BTreeMap<String, QueueState>,
+    /// Course picked with `use`, assumed when a command doesn't name one.
+    context: Option<String>,
+    /// Staff passwords and API tokens.
+    credentials: Credentials,
+    /// How long an API token stays valid.
+    token_lifetime: Duration,
 }
 impl Queue {
-    pub fn new() -> Self {
+    pub fn new(config: Config, credentials: Credentials) -> Self {
+        let token_days = config.http.as_ref().map_or(14, |http| http.token_days);
         Self {
-            ics53: QueueState::new("53rocks".to_owned(), "53backup.txt".to_owned()),
-            ics51: QueueState::new("51rocks".to_owned(), "51backup.txt".to_owned()),
+            courses: config
+                .courses
+                .into_iter()
+                .map(|course| (course.id.clone(), QueueState::new(course)))
+                .collect(),
+            context: None,
+            credentials,
+            token_lifetime: Duration::from_secs(token_days * 24 * 60 * 60),
         }
     }
     pub fn load_backup(&mut self) {
-        self.ics51.load_backup();
-        self.ics53.load_backup();
-    }
-    /// Prints help.
-    ///
-    /// `help`
-    pub fn help(&mut self) -> CommandResult {
-        println!("\"add51 <netid>\" - adds the specified netid to the queue for ics51.");
-        println!("\"view51\" - views the queue for ics51.");
-        println!("\"add53 <netid>\" - adds the specified netid to the queue for ics53.");
-        println!("\"view53\" - views the queue for ics53.");
-        Ok(())
-    }
-    pub fn process_command(&mut self, command: &str) -> CommandResult {
-        let parts = command
-            .split_ascii_whitespace()
-            .map(|s| s.to_owned())
-            .collect::<Vec<String>>();
-        if parts.is_empty() {
-            return Err("Command is empty.".to_owned());
-        }
-
-        match parts[0].to_lowercase().as_str() {
-            "checkin51" => self.ics51.checkin(&parts),
-            "checkin53" => self.ics53.checkin(&parts),
-            "add51" => self.ics51.add(&parts),
-            "add53" => self.ics53.add(&parts),
-            "pop51" => self.ics51.pop(),
-            "pop53" => self.ics53.pop(),
-            "view51" => self.ics51.view(),
-            "view53" => self.ics53.view(),
-            "clear" => {
-                print!("Enter password:");
-                std::io::stdout().flush().unwrap();
-                let password = read_password().unwrap();
-                if password != self.ics51.password && password != self.ics53.password {
-                    return Err("Invalid password.".to_owned());
-                }
-                if clearscreen::clear().is_err() {
-                    return Err("Failed to clear screen.".to_owned());
-                }
-                Ok(())
+        for course in self.courses.values_mut() {
+            course.load_backup();
+            match Pins::load(&course.config.pin_file()) {
+                Ok(pins) => course.pins = pins,
+                Err(err) => println!("{}", err),
             }
-            "stats51" => self.ics51.stats(&parts),
-            "stats53" => self.ics53.stats(&parts),
-            "reset51" => self.ics51.reset(),
-            "reset53" => self.ics53.reset(),
-            "lock51" => self.ics51.lock(),
-            "lock53" => self.ics53.lock(),
-            "unlock51" => self.ics51.unlock(),
-            "unlock53" => self.ics53.unlock(),
-            "help" => self.help(),
-            "quit" => {
-                print!("Enter password:");
-                std::io::stdout().flush().unwrap();
-                let password = read_password().unwrap();
-                if password != self.ics51.password && password != self.ics53.password {
-                    return Err("Invalid password.".to_owned());
+            course.catch_up();
+            course.close_stale_shifts();
+            course.seed_staff();
+            course.warn_problems();
+
+            for net_id in course.staff.keys() {
+                if !self.credentials.contains(net_id) {
+                    println!(
+                        "Staff member {} has no password, set one with \"queue53 passwd {}\".",
+                        net_id, net_id
+                    );
                 }
-                println!("Exiting...");
-                self.ics51.save_backup();
-                self.ics53.save_backup();
-                exit(0);
             }
-            "load51" => self.ics51.load(&parts),
-            "load53" => self.ics53.load(&parts),
-            "save51" => self.ics51.save(&parts),
-            "save53" => self.ics53.save(&parts),
-            "add_staff51" => self.ics51.add_staff(&parts),
-            "add_staff53" => self.ics53.add_staff(&parts),
-            "load_roster51" => self.ics51.load_roster(&parts),
-            "load_roster53" => self.ics53.load_roster(&parts),
-            _ => Err("Unknown command.".to_owned()),
         }
     }
+    /// Checks out all staff and saves every course, before the program exits.
+    pub fn shutdown(&mut self) {
+        for course in self.courses.values_mut() {
+            course.checkout_all();
+            course.save_backup();
+        }
+    }
+    /// Finds a course by its id or one of its aliases. Case insensitive.
+    pub fn find_course(&self, name: &str) -> Option<&str> {
+        self.courses
+            .values()
+            .find(|course| course.config.names().any(|n| n.eq_ignore_ascii_case(name)))
+            .map(|course| course.config.id.as_str())
+    }
+    /// Checks a staff login. With a course, the staff member has to be on
+    /// its staff, otherwise on any course's. Returns their netid.
+    fn authenticate(&self, login: &Login, course_id: Option<&str>) -> Result<String, String> {
+        let net_id = self.credentials.check(login)?;
+        self.authorize(&net_id, course_id)?;
+        Ok(net_id)
+    }
+    /// Checks that `net_id` is staff of the course, or of any course.
+    fn authorize(&self, net_id: &str, course_id: Option<&str>) -> CommandResult {
+        let is_staff = match course_id {
+            Some(course_id) => self.courses[course_id].staff.contains_key(net_id),
+            None => self
+                .courses
+                .values()
+                .any(|course| course.staff.contains_key(net_id)),
+        };
+        if !is_staff {
+            return Err(format!(
+                "{} is not staff of {}.",
+                net_id,
+                course_id.unwrap_or("any course")
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// How often calls, schedules and capacity are checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Drops students who didn't answer their call in time, opens and closes
+/// courses on their schedules and locks them when they are full, in the
+/// background for as long as the program runs.
+fn watch(queue: Arc<Mutex<Queue>>) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(CHECK_INTERVAL);
+        let mut queue = queue.lock().unwrap();
+        for course in queue.courses.values_mut() {
+            course.expire_calls(Utc::now());
+            course.follow_schedule(Utc::now());
+            course.check_capacity(Utc::now());
+        }
+    });
+}
+
+/// Checks out all staff and saves every course on Ctrl-C, or when the
+/// machine shuts down, the same as `quit`.
+fn handle_signals(queue: Arc<Mutex<Queue>>) {
+    let mut signals = match Signals::new([SIGINT, SIGTERM]) {
+        Ok(signals) => signals,
+        Err(err) => {
+            println!("Failed to handle signals: {}", err);
+            return;
+        }
+    };
+    std::thread::spawn(move || {
+        if signals.forever().next().is_some() {
+            let _ = queue.lock().unwrap().quit();
+        }
+    });
 }
 
 fn main() {
-    // let vec = vec![(Duration::default(), "One".to_owned()),(Duration::default(), "Two".to_owned()),
-    // (Duration::default(), "Three".to_owned()),(Duration::default(), "Four".to_owned()),];
+    let mut args = std::env::args().skip(1);
+    let mut config_path = DEFAULT_CONFIG_PATH.to_owned();
+    let mut passwd = None;
+    let mut display = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = args.next().unwrap_or(config_path),
+            "passwd" => passwd = args.next(),
+            // Everything after "display" is its own.
+            "display" => display = Some(args.by_ref().collect::<Vec<String>>()),
+            _ => {
+                println!("Usage: queue53 [--config <path>] [passwd <netid> | display [options]]");
+                exit(1);
+            }
+        }
+    }
 
-    // println!("{}", serde_json::to_string_pretty(&vec).unwrap());
+    let config = match Config::load(&config_path) {
+        Ok(config) => config,
+        Err(err) => {
+            println!("Error: {}", err);
+            exit(1);
+        }
+    };
 
-    // panic!();
+    // The lobby monitor, which never needs a login.
+    if let Some(args) = display {
+        if let Err(err) = display::run(config, &args) {
+            println!("Error: {}", err);
+            exit(1);
+        }
+        return;
+    }
 
-    let mut queue = Queue::new();
+    let credentials_path = config
+        .credentials_file
+        .clone()
+        .unwrap_or_else(|| DEFAULT_CREDENTIALS_PATH.to_owned());
+    let mut credentials = match Credentials::load(&credentials_path) {
+        Ok(credentials) => credentials,
+        Err(err) => {
+            println!("Error: {}", err);
+            exit(1);
+        }
+    };
+
+    // Setting a password from the machine itself, how the first staff
+    // members get one. Only for netids without one, anyone at the kiosk can
+    // run this.
+    if let Some(net_id) = passwd {
+        let net_id = net_id.to_lowercase();
+        if credentials.contains(&net_id) {
+            println!(
+                "Error: {} already has a password, change it with \"passwd\" after logging in.",
+                net_id
+            );
+            exit(1);
+        }
+        match prompt_new_password(&net_id)
+            .and_then(|password| credentials.set_password(&net_id, &password))
+        {
+            Ok(()) => println!("Password set."),
+            Err(err) => println!("Error: {}", err),
+        }
+        return;
+    }
+
+    let http = config.http.clone();
+    let mut queue = Queue::new(config, credentials);
     queue.load_backup();
+    let queue = Arc::new(Mutex::new(queue));
+    watch(Arc::clone(&queue));
+    handle_signals(Arc::clone(&queue));
+
+    if let Some(http) = http {
+        if let Err(err) = http::serve(Arc::clone(&queue), &http.listen) {
+            println!("Error: {}", err);
+            exit(1);
+        }
+        println!("HTTP API listening on {}.", http.listen);
+    }
+
     let mut buffer = String::new();
     loop {
-        std::io::stdin().read_line(&mut buffer).expect("Hmmmmm");
-        if let Err(err) = queue.process_command(&buffer) {
+        if std::io::stdin().read_line(&mut buffer).expect("Hmmmmm") == 0 {
+            // Stdin closed, nothing more will come.
+            queue.lock().unwrap().shutdown();
+            break;
+        }
+        if let Err(err) = commands::process_command(&queue, &buffer) {
             println!("Error: {}", err);
         }
         buffer.clear();
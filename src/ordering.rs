@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{journal::Event, start_of_today, CommandResult, QueueState, QueuedStudent};
+
+/// Decides the order students are helped in.
+///
+/// The queue itself always stays in the order students joined. A policy
+/// only works out who goes first, fresh every time it is asked, so `pop`,
+/// `view` and positions always agree and switching policies loses nothing.
+/// Missed calls and requeues are applied on top, by [`QueueState::ordered`].
+pub trait OrderingPolicy {
+    /// The queue in the order students will be helped.
+    fn order<'a>(&self, state: &'a QueueState) -> Vec<&'a QueuedStudent>;
+}
+
+/// First come, first served.
+pub struct Fifo;
+impl OrderingPolicy for Fifo {
+    fn order<'a>(&self, state: &'a QueueState) -> Vec<&'a QueuedStudent> {
+        state.queue.iter().collect()
+    }
+}
+
+/// Students who haven't been helped yet today go ahead of those who have,
+/// otherwise first come, first served.
+pub struct NotHelpedToday;
+impl OrderingPolicy for NotHelpedToday {
+    fn order<'a>(&self, state: &'a QueueState) -> Vec<&'a QueuedStudent> {
+        let today = start_of_today();
+        let helped_today = |queued: &QueuedStudent| {
+            state.students.get(&queued.net_id).is_some_and(|student| {
+                student
+                    .queue_times
+                    .iter()
+                    .any(|visit| visit.was_helped() && visit.at >= today)
+            })
+        };
+        let mut order = state.queue.iter().collect::<Vec<&QueuedStudent>>();
+        // Stable, so each group stays first come, first served.
+        order.sort_by_key(|queued| helped_today(queued));
+        order
+    }
+}
+
+/// Takes one student from each topic in turn, so one busy topic doesn't
+/// hold up all the others. Topics take turns in the order their first
+/// student joined, students without a topic count as one more topic.
+pub struct TopicRoundRobin;
+impl OrderingPolicy for TopicRoundRobin {
+    fn order<'a>(&self, state: &'a QueueState) -> Vec<&'a QueuedStudent> {
+        let mut topics: Vec<Vec<&QueuedStudent>> = Vec::new();
+        let mut index: HashMap<Option<&str>, usize> = HashMap::new();
+        for queued in &state.queue {
+            let topic = queued.question.topic.as_deref();
+            let i = *index.entry(topic).or_insert_with(|| {
+                topics.push(Vec::new());
+                topics.len() - 1
+            });
+            topics[i].push(queued);
+        }
+
+        let mut order = Vec::with_capacity(state.queue.len());
+        for round in 0.. {
+            let before = order.len();
+            order.extend(topics.iter().filter_map(|topic| topic.get(round)));
+            if order.len() == before {
+                break;
+            }
+        }
+        order
+    }
+}
+
+/// Students with an accommodation go first, otherwise first come, first
+/// served.
+pub struct Accommodation;
+impl OrderingPolicy for Accommodation {
+    fn order<'a>(&self, state: &'a QueueState) -> Vec<&'a QueuedStudent> {
+        let mut order = state.queue.iter().collect::<Vec<&QueuedStudent>>();
+        order.sort_by_key(|queued| {
+            !state
+                .students
+                .get(&queued.net_id)
+                .is_some_and(|student| student.accommodation)
+        });
+        order
+    }
+}
+
+/// The ordering policies a course can pick in its config.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ordering {
+    #[default]
+    Fifo,
+    NotHelpedToday,
+    TopicRoundRobin,
+    Accommodation,
+}
+impl Ordering {
+    pub fn policy(self) -> &'static dyn OrderingPolicy {
+        match self {
+            Ordering::Fifo => &Fifo,
+            Ordering::NotHelpedToday => &NotHelpedToday,
+            Ordering::TopicRoundRobin => &TopicRoundRobin,
+            Ordering::Accommodation => &Accommodation,
+        }
+    }
+}
+
+impl QueueState {
+    /// The queue in the order students will be helped, by the course's
+    /// policy. Students put back by staff go first, and students who missed
+    /// a call wait for the ones who were next after them, wherever the
+    /// policy puts those now.
+    pub fn ordered(&self) -> Vec<&QueuedStudent> {
+        let mut order = self.config.ordering.policy().order(self);
+        // Stable, so the policy still decides among the rest.
+        order.sort_by_key(|queued| !queued.requeued);
+
+        let missed = order
+            .iter()
+            .filter(|queued| !queued.behind.is_empty())
+            .map(|queued| queued.net_id.as_str())
+            .collect::<Vec<&str>>();
+        for net_id in missed {
+            let from = order
+                .iter()
+                .position(|queued| queued.net_id == net_id)
+                .unwrap();
+            let behind = &order[from].behind;
+            let Some(last) = order
+                .iter()
+                .rposition(|queued| behind.contains(&queued.net_id))
+            else {
+                continue;
+            };
+            if last > from {
+                let queued = order.remove(from);
+                order.insert(last, queued);
+            }
+        }
+        order
+    }
+
+    /// Gives a student priority under the "accommodation" ordering, or
+    /// takes it away.
+    ///
+    /// `accommodation <netid> <on|off>`
+    pub fn set_accommodation(&mut self, net_id: &str, on: &str) -> CommandResult {
+        let on = match on {
+            "on" => true,
+            "off" => false,
+            _ => return Err("Usage: \"accommodation <netid> <on|off>\".".to_owned()),
+        };
+        if !self.students.contains_key(net_id) {
+            return Err(format!("{} is not on the roster.", net_id));
+        }
+
+        self.record(Event::AccommodationSet {
+            net_id: net_id.to_owned(),
+            on,
+        })?;
+
+        if on {
+            println!("{} now has an accommodation.", net_id);
+        } else {
+            println!("{} no longer has an accommodation.", net_id);
+        }
+        if self.config.ordering != Ordering::Accommodation {
+            println!(
+                "{} doesn't order by accommodation, set ordering = \"accommodation\" in its config.",
+                self.config.id
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{NaiveDateTime, Utc};
+
+/// Format of the timestamp in snapshot file names. Sorts chronologically.
+const SNAPSHOT_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Replaces the file at `path` with `contents` without ever leaving a
+/// truncated or half-written file behind.
+///
+/// The contents go to a temp file next to `path`, get fsync'd, and are then
+/// renamed over `path`. Renames within a directory are atomic, so a crash
+/// leaves either the old file or the new one.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_path, path)?;
+
+    // Make the rename itself durable. Not every platform lets a directory be
+    // opened, so this is best effort.
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// A rotating set of timestamped copies of a backup file.
+pub struct Snapshots {
+    dir: PathBuf,
+    /// File name prefix, the backup file's name.
+    prefix: String,
+    /// How many snapshots to keep around.
+    keep: usize,
+    /// Minimum time between two snapshots.
+    interval: Duration,
+}
+impl Snapshots {
+    pub fn new(backup_file: &str, dir: Option<&str>, keep: usize, interval: Duration) -> Self {
+        let backup_file = Path::new(backup_file);
+        let prefix = backup_file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "backup".to_owned());
+        let dir = match dir {
+            Some(dir) => PathBuf::from(dir),
+            None => backup_file.with_file_name(format!("{}.snapshots", prefix)),
+        };
+        Self {
+            dir,
+            prefix,
+            keep,
+            interval,
+        }
+    }
+
+    /// Every snapshot on disk, newest first.
+    pub fn newest_first(&self) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut snapshots = entries
+            .flatten()
+            .filter(|entry| self.timestamp_of(&entry.path()).is_some())
+            .map(|entry| entry.path())
+            .collect::<Vec<PathBuf>>();
+        snapshots.sort();
+        snapshots.reverse();
+        snapshots
+    }
+
+    fn timestamp_of(&self, path: &Path) -> Option<NaiveDateTime> {
+        let name = path.file_name()?.to_str()?;
+        let time = name
+            .strip_prefix(&self.prefix)?
+            .strip_prefix('-')?
+            .strip_suffix(".json")?;
+        NaiveDateTime::parse_from_str(time, SNAPSHOT_TIME_FORMAT).ok()
+    }
+
+    /// Writes a new snapshot if the newest one is older than the interval,
+    /// then deletes the oldest ones beyond the number to keep.
+    pub fn take(&self, contents: &[u8]) -> io::Result<()> {
+        if self.keep == 0 {
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        let snapshots = self.newest_first();
+        if let Some(newest) = snapshots.first().and_then(|path| self.timestamp_of(path)) {
+            let age = (now.naive_utc() - newest).to_std().unwrap_or_default();
+            if age < self.interval {
+                return Ok(());
+            }
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!(
+            "{}-{}.json",
+            self.prefix,
+            now.format(SNAPSHOT_TIME_FORMAT)
+        ));
+        write_atomic(&path, contents)?;
+
+        for old in snapshots.iter().skip(self.keep - 1) {
+            fs::remove_file(old)?;
+        }
+        Ok(())
+    }
+}
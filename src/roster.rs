@@ -0,0 +1,602 @@
+use std::{collections::HashMap, io::Read};
+
+use serde::Deserialize;
+
+use crate::Student;
+
+/// A column in a roster file, by position (from 0) or by header name.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ColumnRef {
+    Index(usize),
+    Header(String),
+}
+
+/// Where each field lives in a roster CSV.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RosterFormat {
+    /// Whether the first row holds column names.
+    #[serde(default)]
+    pub header: bool,
+    /// Falls back to the part of `email` before the "@" when missing.
+    #[serde(default)]
+    pub netid: Option<ColumnRef>,
+    #[serde(default)]
+    pub first: Option<ColumnRef>,
+    #[serde(default)]
+    pub last: Option<ColumnRef>,
+    /// A single "Last, First" or "First Last" column, used instead of
+    /// `first` and `last`.
+    #[serde(default)]
+    pub name: Option<ColumnRef>,
+    #[serde(default)]
+    pub email: Option<ColumnRef>,
+    #[serde(default)]
+    pub section: Option<ColumnRef>,
+    #[serde(default)]
+    pub student_id: Option<ColumnRef>,
+}
+
+/// Names of the built in formats.
+pub const PRESETS: &[&str] = &["legacy", "canvas", "gradescope", "registrar"];
+
+fn header(name: &str) -> Option<ColumnRef> {
+    Some(ColumnRef::Header(name.to_owned()))
+}
+
+impl RosterFormat {
+    /// A built in format, by name.
+    pub fn preset(name: &str) -> Option<Self> {
+        let format = match name.to_lowercase().as_str() {
+            // "last,first,netid" without a header, what load_roster always took.
+            "legacy" => RosterFormat {
+                header: false,
+                last: Some(ColumnRef::Index(0)),
+                first: Some(ColumnRef::Index(1)),
+                netid: Some(ColumnRef::Index(2)),
+                ..Default::default()
+            },
+            // Canvas gradebook export.
+            "canvas" => RosterFormat {
+                header: true,
+                name: header("Student"),
+                netid: header("SIS Login ID"),
+                student_id: header("SIS User ID"),
+                section: header("Section"),
+                ..Default::default()
+            },
+            // Gradescope roster export.
+            "gradescope" => RosterFormat {
+                header: true,
+                first: header("First Name"),
+                last: header("Last Name"),
+                email: header("Email"),
+                student_id: header("SID"),
+                section: header("Sections"),
+                ..Default::default()
+            },
+            // Registrar class roster.
+            "registrar" => RosterFormat {
+                header: true,
+                name: header("Name"),
+                email: header("Email"),
+                student_id: header("Student ID"),
+                section: header("Sec"),
+                ..Default::default()
+            },
+            _ => return None,
+        };
+        Some(format)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.netid.is_none() && self.email.is_none() {
+            return Err("Roster format needs a netid or email column.".to_owned());
+        }
+        if self.name.is_none() && (self.first.is_none() || self.last.is_none()) {
+            return Err("Roster format needs a name column, or first and last.".to_owned());
+        }
+        Ok(())
+    }
+}
+
+/// A course's roster format in the config: a preset's name or a full
+/// column mapping.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum RosterFormatConfig {
+    Preset(String),
+    Custom(RosterFormat),
+}
+impl Default for RosterFormatConfig {
+    fn default() -> Self {
+        RosterFormatConfig::Preset("legacy".to_owned())
+    }
+}
+impl RosterFormatConfig {
+    pub fn resolve(&self) -> Result<RosterFormat, String> {
+        match self {
+            RosterFormatConfig::Preset(name) => RosterFormat::preset(name).ok_or_else(|| {
+                format!(
+                    "Unknown roster format \"{}\", one of: {}.",
+                    name,
+                    PRESETS.join(", ")
+                )
+            }),
+            RosterFormatConfig::Custom(format) => Ok(format.clone()),
+        }
+    }
+}
+
+/// A row that couldn't be imported.
+#[derive(Debug)]
+pub struct RowError {
+    /// Line in the file, starting at 1.
+    pub line: u64,
+    pub message: String,
+}
+
+pub struct ParsedRoster {
+    /// Students key'd by netid, in file order.
+    pub students: Vec<(String, Student)>,
+    pub errors: Vec<RowError>,
+}
+
+/// A roster that was read and shown to staff, waiting for them to confirm
+/// loading it.
+pub struct PendingRoster {
+    pub students: HashMap<String, Student>,
+    /// Replaces the current roster instead of merging into it.
+    pub replace: bool,
+    /// Lines that couldn't be imported.
+    pub skipped: usize,
+}
+
+/// [`RosterFormat`] with every column resolved to a position.
+struct Columns {
+    netid: Option<usize>,
+    first: Option<usize>,
+    last: Option<usize>,
+    name: Option<usize>,
+    email: Option<usize>,
+    section: Option<usize>,
+    student_id: Option<usize>,
+}
+
+/// The position of a column. `None` when the format doesn't have it or the
+/// header row doesn't name it, [`parse`] decides which ones it can't do
+/// without.
+fn resolve_column(
+    column: &Option<ColumnRef>,
+    headers: Option<&csv::StringRecord>,
+) -> Result<Option<usize>, String> {
+    let Some(column) = column else {
+        return Ok(None);
+    };
+    match column {
+        ColumnRef::Index(index) => Ok(Some(*index)),
+        ColumnRef::Header(name) => {
+            let Some(headers) = headers else {
+                return Err(format!(
+                    "Column \"{}\" is named but the format has no header row.",
+                    name
+                ));
+            };
+            Ok(headers.iter().position(|header| {
+                header
+                    .trim_start_matches('\u{feff}')
+                    .trim()
+                    .eq_ignore_ascii_case(name.trim())
+            }))
+        }
+    }
+}
+
+/// The first of `columns` the format names but the header row doesn't, as
+/// an error.
+fn missing_column(columns: &[(&Option<ColumnRef>, Option<usize>)]) -> String {
+    match columns
+        .iter()
+        .find(|(column, index)| column.is_some() && index.is_none())
+    {
+        Some((Some(ColumnRef::Header(name)), _)) => {
+            format!("Column \"{}\" is missing from the header row.", name)
+        }
+        _ => "Roster is missing a column.".to_owned(),
+    }
+}
+
+/// Splits "Last, First" or "First Last" into `(first, last)`.
+fn split_name(name: &str) -> (String, String) {
+    if let Some((last, first)) = name.split_once(',') {
+        return (first.trim().to_owned(), last.trim().to_owned());
+    }
+    match name.trim().rsplit_once(' ') {
+        Some((first, last)) => (first.trim().to_owned(), last.trim().to_owned()),
+        None => (String::new(), name.trim().to_owned()),
+    }
+}
+
+/// Reads a roster CSV. Rows that can't be imported are reported in
+/// [`ParsedRoster::errors`] instead of stopping the import.
+pub fn parse(reader: impl Read, format: &RosterFormat) -> Result<ParsedRoster, String> {
+    format.validate()?;
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(format.header)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+
+    let headers = if format.header {
+        match reader.headers() {
+            Ok(headers) => Some(headers.clone()),
+            Err(err) => return Err(format!("Failed to read header row: {}", err)),
+        }
+    } else {
+        None
+    };
+    let headers = headers.as_ref();
+    let columns = Columns {
+        netid: resolve_column(&format.netid, headers)?,
+        first: resolve_column(&format.first, headers)?,
+        last: resolve_column(&format.last, headers)?,
+        name: resolve_column(&format.name, headers)?,
+        email: resolve_column(&format.email, headers)?,
+        section: resolve_column(&format.section, headers)?,
+        student_id: resolve_column(&format.student_id, headers)?,
+    };
+    // Email, section and student ID are optional, the netid and name aren't.
+    if columns.netid.is_none() && columns.email.is_none() {
+        return Err(missing_column(&[
+            (&format.netid, columns.netid),
+            (&format.email, columns.email),
+        ]));
+    }
+    if columns.name.is_none() && (columns.first.is_none() || columns.last.is_none()) {
+        return Err(missing_column(&[
+            (&format.name, columns.name),
+            (&format.first, columns.first),
+            (&format.last, columns.last),
+        ]));
+    }
+
+    let mut students = Vec::new();
+    let mut errors = Vec::new();
+    let mut seen: HashMap<String, u64> = HashMap::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                let line = err.position().map(|pos| pos.line()).unwrap_or(0);
+                errors.push(RowError {
+                    line,
+                    message: err.to_string(),
+                });
+                continue;
+            }
+        };
+        let line = record.position().map(|pos| pos.line()).unwrap_or(0);
+
+        // Blank lines and rows of empty cells, e.g. trailing commas.
+        if record.iter().all(|field| field.is_empty()) {
+            continue;
+        }
+
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|column| record.get(column))
+                .map(|field| field.trim_start_matches('\u{feff}'))
+                .filter(|field| !field.is_empty())
+                .map(|field| field.to_owned())
+        };
+
+        let email = field(columns.email);
+        let netid = field(columns.netid).or_else(|| {
+            email
+                .as_ref()
+                .and_then(|email| email.split_once('@'))
+                .map(|(local, _)| local.to_owned())
+        });
+        let Some(netid) = netid.map(|netid| netid.to_lowercase()) else {
+            errors.push(RowError {
+                line,
+                message: "No netid.".to_owned(),
+            });
+            continue;
+        };
+
+        let (first, last) = match (field(columns.first), field(columns.last)) {
+            (Some(first), Some(last)) => (first, last),
+            _ => match field(columns.name) {
+                Some(name) => split_name(&name),
+                None => {
+                    errors.push(RowError {
+                        line,
+                        message: format!("No name for {}.", netid),
+                    });
+                    continue;
+                }
+            },
+        };
+
+        if let Some(first_line) = seen.get(&netid) {
+            errors.push(RowError {
+                line,
+                message: format!("Duplicate netid {}, first on line {}.", netid, first_line),
+            });
+            continue;
+        }
+        seen.insert(netid.clone(), line);
+
+        students.push((
+            netid,
+            Student {
+                first: first.to_lowercase(),
+                last: last.to_lowercase(),
+                email,
+                section: field(columns.section),
+                student_id: field(columns.student_id),
+                dropped: false,
+                accommodation: false,
+                queue_times: Vec::default(),
+                missed_calls: Vec::default(),
+            },
+        ));
+    }
+
+    Ok(ParsedRoster { students, errors })
+}
+
+/// What merging a new roster into the current one would change.
+#[derive(Debug, Default)]
+pub struct RosterDiff {
+    /// Netids not on the current roster.
+    pub added: Vec<String>,
+    /// Active students missing from the new roster.
+    pub dropped: Vec<String>,
+    /// Dropped students who are back on the new roster.
+    pub rejoined: Vec<String>,
+    /// Students whose name changed, with their old name.
+    pub renamed: Vec<(String, String)>,
+    /// Students whose email, section or student ID changed, with which of
+    /// them did.
+    pub updated: Vec<(String, Vec<&'static str>)>,
+}
+impl RosterDiff {
+    pub fn new(current: &HashMap<String, Student>, new: &HashMap<String, Student>) -> Self {
+        let mut diff = RosterDiff::default();
+        for (net_id, student) in new {
+            match current.get(net_id) {
+                None => diff.added.push(net_id.clone()),
+                Some(old) => {
+                    if old.dropped {
+                        diff.rejoined.push(net_id.clone());
+                    }
+                    if old.first != student.first || old.last != student.last {
+                        diff.renamed
+                            .push((net_id.clone(), format!("{} {}", old.first, old.last)));
+                    }
+                    let fields = [
+                        ("email", old.email != student.email),
+                        ("section", old.section != student.section),
+                        ("student ID", old.student_id != student.student_id),
+                    ]
+                    .into_iter()
+                    .filter(|(_, changed)| *changed)
+                    .map(|(field, _)| field)
+                    .collect::<Vec<&str>>();
+                    if !fields.is_empty() {
+                        diff.updated.push((net_id.clone(), fields));
+                    }
+                }
+            }
+        }
+        for (net_id, student) in current {
+            if !student.dropped && !new.contains_key(net_id) {
+                diff.dropped.push(net_id.clone());
+            }
+        }
+
+        diff.added.sort();
+        diff.dropped.sort();
+        diff.rejoined.sort();
+        diff.renamed.sort();
+        diff.updated.sort();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.dropped.is_empty()
+            && self.rejoined.is_empty()
+            && self.renamed.is_empty()
+            && self.updated.is_empty()
+    }
+
+    /// Prints the diff. Dropped students are named as they are on the
+    /// current roster, everyone else as on the new one.
+    pub fn print(&self, current: &HashMap<String, Student>, new: &HashMap<String, Student>) {
+        let name = |students: &HashMap<String, Student>, net_id: &str| {
+            students
+                .get(net_id)
+                .map(|student| format!("{} ({} {})", net_id, student.first, student.last))
+                .unwrap_or_else(|| net_id.to_owned())
+        };
+
+        println!("Added ({}):", self.added.len());
+        for net_id in &self.added {
+            println!("  + {}", name(new, net_id));
+        }
+        println!("Dropped ({}):", self.dropped.len());
+        for net_id in &self.dropped {
+            println!("  - {}", name(current, net_id));
+        }
+        if !self.rejoined.is_empty() {
+            println!("Rejoined ({}):", self.rejoined.len());
+            for net_id in &self.rejoined {
+                println!("  + {}", name(new, net_id));
+            }
+        }
+        println!("Renamed ({}):", self.renamed.len());
+        for (net_id, old_name) in &self.renamed {
+            println!("  ~ {}, was {}", name(new, net_id), old_name);
+        }
+        // Just which fields, student IDs stay off the screen.
+        println!("Updated ({}):", self.updated.len());
+        for (net_id, fields) in &self.updated {
+            println!("  ~ {}: {}", name(new, net_id), fields.join(", "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_preset(csv: &str, preset: &str) -> ParsedRoster {
+        parse(csv.as_bytes(), &RosterFormat::preset(preset).unwrap()).unwrap()
+    }
+
+    fn net_ids(parsed: &ParsedRoster) -> Vec<&str> {
+        parsed
+            .students
+            .iter()
+            .map(|(net_id, _)| net_id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn splits_names() {
+        assert_eq!(split_name("Doe, Jane"), ("Jane".into(), "Doe".into()));
+        assert_eq!(split_name("Jane Q Doe"), ("Jane Q".into(), "Doe".into()));
+        assert_eq!(split_name(" Cher "), ("".into(), "Cher".into()));
+    }
+
+    #[test]
+    fn legacy_rows_in_order() {
+        let parsed = parse_preset("Doe,Jane,JDoe\nSmith,Bob,bsmith\n", "legacy");
+        assert!(parsed.errors.is_empty());
+        assert_eq!(net_ids(&parsed), ["jdoe", "bsmith"]);
+        let (_, jane) = &parsed.students[0];
+        assert_eq!((jane.first.as_str(), jane.last.as_str()), ("jane", "doe"));
+    }
+
+    #[test]
+    fn quoted_commas_stay_in_their_field() {
+        let csv = "Student,ID,SIS User ID,SIS Login ID,Section\n\
+                   \"Doe, Jane\",1,12345678,jdoe,\"A1, Lab\"\n";
+        let parsed = parse_preset(csv, "canvas");
+        assert!(parsed.errors.is_empty());
+        let (net_id, jane) = &parsed.students[0];
+        assert_eq!(net_id, "jdoe");
+        assert_eq!((jane.first.as_str(), jane.last.as_str()), ("jane", "doe"));
+        assert_eq!(jane.section.as_deref(), Some("A1, Lab"));
+        assert_eq!(jane.student_id.as_deref(), Some("12345678"));
+    }
+
+    #[test]
+    fn bom_before_the_header_is_ignored() {
+        let csv = "\u{feff}First Name,Last Name,SID,Email,Sections\n\
+                   Jane,Doe,123,jdoe@uni.edu,A\n";
+        let parsed = parse_preset(csv, "gradescope");
+        assert!(parsed.errors.is_empty());
+        // The netid comes from the email when there is no netid column.
+        assert_eq!(net_ids(&parsed), ["jdoe"]);
+    }
+
+    #[test]
+    fn bad_rows_are_reported_by_line() {
+        let csv = "Name,Email,Student ID,Sec\n\
+                   \"Doe, Jane\",jdoe@uni.edu,1,A\n\
+                   \"Nobody, No\",,2,A\n\
+                   ,bsmith@uni.edu,3,A\n\
+                   ,,,\n\
+                   \"Smith, Anna\",asmith@uni.edu,4,A\n";
+        let parsed = parse_preset(csv, "registrar");
+        assert_eq!(net_ids(&parsed), ["jdoe", "asmith"]);
+        let errors = parsed
+            .errors
+            .iter()
+            .map(|error| (error.line, error.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [(3, "No netid."), (4, "No name for bsmith.")]);
+    }
+
+    #[test]
+    fn duplicates_keep_the_first() {
+        let parsed = parse_preset(
+            "Doe,Jane,jdoe\nSmith,Bob,bsmith\nDoe,Janet,JDOE\n",
+            "legacy",
+        );
+        assert_eq!(net_ids(&parsed), ["jdoe", "bsmith"]);
+        assert_eq!(parsed.students[0].1.first, "jane");
+        assert_eq!(parsed.errors.len(), 1);
+        assert_eq!(parsed.errors[0].line, 3);
+        assert_eq!(
+            parsed.errors[0].message,
+            "Duplicate netid jdoe, first on line 1."
+        );
+    }
+
+    #[test]
+    fn missing_optional_column_is_left_out() {
+        let roster = parse(
+            "Student,SIS User ID,SIS Login ID\n\"Doe, Jane\",1,jdoe\n".as_bytes(),
+            &RosterFormat::preset("canvas").unwrap(),
+        )
+        .unwrap();
+        assert!(roster.errors.is_empty());
+        let (netid, student) = &roster.students[0];
+        assert_eq!(netid, "jdoe");
+        assert_eq!(student.student_id.as_deref(), Some("1"));
+        assert_eq!(student.section, None);
+    }
+
+    #[test]
+    fn missing_netid_column_fails_the_whole_file() {
+        let err = parse(
+            "Student,SIS User ID,Section\n\"Doe, Jane\",1,A\n".as_bytes(),
+            &RosterFormat::preset("canvas").unwrap(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err,
+            "Column \"SIS Login ID\" is missing from the header row."
+        );
+    }
+
+    #[test]
+    fn new_student_ids_are_an_update() {
+        let students = |parsed: ParsedRoster| {
+            parsed
+                .students
+                .into_iter()
+                .collect::<HashMap<String, Student>>()
+        };
+        let current = students(parse_preset("Doe,Jane,jdoe\n", "legacy"));
+        let new = students(parse_preset(
+            "Name,Email,Student ID,Sec\n\"Doe, Jane\",jdoe@uni.edu,12345678,A\n",
+            "registrar",
+        ));
+
+        let diff = RosterDiff::new(&current, &new);
+        assert!(!diff.is_empty());
+        assert_eq!(
+            diff.updated,
+            [("jdoe".to_owned(), vec!["email", "section", "student ID"])]
+        );
+        assert!(RosterDiff::new(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn incomplete_formats_are_rejected() {
+        let format = RosterFormat {
+            header: false,
+            first: Some(ColumnRef::Index(0)),
+            ..Default::default()
+        };
+        assert!(parse("".as_bytes(), &format).is_err());
+    }
+}
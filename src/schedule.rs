@@ -0,0 +1,305 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    average, capacity::LockReason, format_duration, journal::Event, CommandResult, Outcome,
+    QueueState, Visit,
+};
+
+/// How many days ahead `schedule` lists.
+const UPCOMING_DAYS: u64 = 7;
+
+/// Reads a "HH:MM" time of day.
+fn deserialize_time<'de, D>(deserializer: D) -> Result<NaiveTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let time = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&time, "%H:%M")
+        .map_err(|_| serde::de::Error::custom(format!("invalid time \"{}\", expected HH:MM", time)))
+}
+
+/// Office hours that repeat every week.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WeeklyHours {
+    /// "mon", "tue", ...
+    pub day: Weekday,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub start: NaiveTime,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub end: NaiveTime,
+}
+
+/// Office hours on a single date, e.g. before an exam.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExtraHours {
+    pub date: NaiveDate,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub start: NaiveTime,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub end: NaiveTime,
+}
+
+/// When a course holds office hours. The queue opens at the start of each
+/// session, locks a few minutes before its end and closes at the end.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Schedule {
+    #[serde(default)]
+    pub weekly: Vec<WeeklyHours>,
+    /// One-off sessions, on top of the weekly ones.
+    #[serde(default)]
+    pub extra: Vec<ExtraHours>,
+    /// Dates without the weekly sessions. Extra sessions still happen, so a
+    /// holiday plus an extra session moves the hours.
+    #[serde(default)]
+    pub holidays: Vec<NaiveDate>,
+    /// Minutes before the end of a session that the queue locks, so the
+    /// students in it can still be helped.
+    #[serde(default = "default_lock_before_end")]
+    pub lock_before_end_minutes: u64,
+}
+fn default_lock_before_end() -> u64 {
+    10
+}
+
+/// A session from the schedule, on a particular date.
+#[derive(Debug, Clone)]
+pub struct ScheduledSession {
+    /// "YYYY-MM-DDTHH:MM" of its start, unique within the course.
+    pub id: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl Schedule {
+    /// Every session on `date`, earliest first.
+    pub fn sessions_on(&self, date: NaiveDate) -> Vec<ScheduledSession> {
+        let mut hours = self
+            .extra
+            .iter()
+            .filter(|extra| extra.date == date)
+            .map(|extra| (extra.start, extra.end))
+            .collect::<Vec<(NaiveTime, NaiveTime)>>();
+        if !self.holidays.contains(&date) {
+            hours.extend(
+                self.weekly
+                    .iter()
+                    .filter(|weekly| weekly.day == date.weekday())
+                    .map(|weekly| (weekly.start, weekly.end)),
+            );
+        }
+        hours.sort();
+        hours.dedup();
+
+        let local = |time: NaiveTime| {
+            Local
+                .from_local_datetime(&date.and_time(time))
+                .earliest()
+                .map(|time| time.with_timezone(&Utc))
+        };
+        hours
+            .into_iter()
+            .filter_map(|(start, end)| {
+                Some(ScheduledSession {
+                    id: format!("{}T{}", date, start.format("%H:%M")),
+                    start: local(start)?,
+                    end: local(end)?,
+                })
+            })
+            .collect()
+    }
+
+    /// The session going on at `now`, if any.
+    pub fn current(&self, now: DateTime<Utc>) -> Option<ScheduledSession> {
+        self.sessions_on(now.with_timezone(&Local).date_naive())
+            .into_iter()
+            .find(|session| session.start <= now && now < session.end)
+    }
+
+    /// The sessions starting after `now`, over the next few days.
+    pub fn upcoming(&self, now: DateTime<Utc>) -> Vec<ScheduledSession> {
+        now.with_timezone(&Local)
+            .date_naive()
+            .iter_days()
+            .take(UPCOMING_DAYS as usize)
+            .flat_map(|date| self.sessions_on(date))
+            .filter(|session| session.start > now)
+            .collect()
+    }
+
+    pub fn lock_before_end(&self) -> Duration {
+        Duration::from_secs(self.lock_before_end_minutes * 60)
+    }
+}
+
+/// The session a course's queue was last opened for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    /// When the queue opened.
+    pub start: DateTime<Utc>,
+    /// When it is scheduled to close.
+    pub end: DateTime<Utc>,
+    /// Locked for the end of the session.
+    #[serde(default)]
+    pub ending: bool,
+    /// Closed, by the schedule or by staff.
+    #[serde(default)]
+    pub closed: bool,
+    /// Locked because the staff on duty couldn't help everyone in time.
+    #[serde(default)]
+    pub full: bool,
+}
+
+impl QueueState {
+    /// The id of the session going on now, if the queue is open for one.
+    pub fn session_id(&self) -> Option<String> {
+        self.session
+            .as_ref()
+            .filter(|session| !session.closed)
+            .map(|session| session.id.clone())
+    }
+
+    /// Whether the course has a schedule and no session is open, so the
+    /// queue stays locked until the next one.
+    pub fn outside_hours(&self) -> bool {
+        self.config.schedule.is_some() && self.session_id().is_none()
+    }
+
+    /// Opens, locks and closes the queue as the course's schedule says, as
+    /// of `now`, and keeps it locked between sessions. Does nothing for
+    /// courses without a schedule.
+    pub fn follow_schedule(&mut self, now: DateTime<Utc>) {
+        if let Err(err) = self.advance_session(now) {
+            println!("{}", err);
+        }
+    }
+    fn advance_session(&mut self, now: DateTime<Utc>) -> CommandResult {
+        let Some(schedule) = &self.config.schedule else {
+            return Ok(());
+        };
+        let lock_before_end = schedule.lock_before_end();
+        let next = schedule.upcoming(now).first().map(|session| session.start);
+
+        if let Some(scheduled) = schedule.current(now) {
+            // Only once per session, so closing early with "close" sticks.
+            if self
+                .session
+                .as_ref()
+                .is_none_or(|session| session.id != scheduled.id)
+            {
+                self.record(Event::Opened {
+                    session: scheduled.id,
+                    end: scheduled.end,
+                })?;
+                println!(
+                    "{} opened for the session until {}.",
+                    self.config.id,
+                    scheduled.end.with_timezone(&Local).format("%H:%M")
+                );
+            }
+        }
+
+        if let Some(session) = self.session.as_ref().filter(|session| !session.closed) {
+            let (end, ending) = (session.end, session.ending);
+            if now >= end {
+                self.record(Event::Closed)?;
+                println!("{} closed, the session is over.", self.config.id);
+            } else if !ending && now + lock_before_end >= end {
+                self.record(Event::SessionEnding)?;
+                println!(
+                    "{} locked, the session ends at {}.",
+                    self.config.id,
+                    end.with_timezone(&Local).format("%H:%M")
+                );
+            }
+        }
+
+        // Also for a new course, after "close" or after staff lock it by hand.
+        if self.session_id().is_none()
+            && !matches!(self.lock_reason, Some(LockReason::OutsideHours { .. }))
+        {
+            self.record(Event::OutsideHours { next })?;
+            println!(
+                "{} locked. {}",
+                self.config.id,
+                LockReason::OutsideHours { next }
+            );
+        }
+        Ok(())
+    }
+
+    /// Prints the current session and the ones coming up.
+    ///
+    /// `schedule`
+    pub fn print_schedule(&mut self) -> CommandResult {
+        let Some(schedule) = &self.config.schedule else {
+            return Err(format!("{} has no schedule.", self.config.id));
+        };
+        if let Some(session) = self.session.as_ref().filter(|session| !session.closed) {
+            println!(
+                "Current session {}, until {}.",
+                session.id,
+                session.end.with_timezone(&Local).format("%H:%M")
+            );
+        }
+
+        let upcoming = schedule.upcoming(Utc::now());
+        if upcoming.is_empty() {
+            println!("No sessions in the next {} days.", UPCOMING_DAYS);
+        }
+        for session in upcoming {
+            println!(
+                "{} {} to {}",
+                session.start.with_timezone(&Local).format("%a %d/%m"),
+                session.start.with_timezone(&Local).format("%H:%M"),
+                session.end.with_timezone(&Local).format("%H:%M")
+            );
+        }
+        Ok(())
+    }
+
+    /// Prints the visits of every session: how they ended and the average
+    /// wait and help. Visits from outside any session are grouped together.
+    ///
+    /// `session_report`
+    pub fn session_report(&mut self) -> CommandResult {
+        let mut sessions: BTreeMap<Option<&str>, Vec<&Visit>> = BTreeMap::new();
+        for visit in self.students.values().flat_map(|s| s.queue_times.iter()) {
+            sessions
+                .entry(visit.session.as_deref())
+                .or_default()
+                .push(visit);
+        }
+        if sessions.is_empty() {
+            println!("No visits yet.");
+        }
+
+        for (session, visits) in sessions {
+            let count = |outcome| {
+                visits
+                    .iter()
+                    .filter(|visit| visit.outcome == outcome)
+                    .count()
+            };
+            let helped = visits
+                .iter()
+                .filter(|visit| visit.was_helped())
+                .collect::<Vec<_>>();
+            println!(
+                "{}: helped {}, left {}, no-shows {}, average wait {}, average help {}",
+                session.unwrap_or("Outside sessions"),
+                helped.len(),
+                count(Outcome::Left),
+                count(Outcome::NoShow),
+                average(helped.iter().map(|visit| visit.wait))
+                    .map_or("-".to_owned(), format_duration),
+                average(helped.iter().filter_map(|visit| visit.help))
+                    .map_or("-".to_owned(), format_duration)
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use std::{collections::BTreeMap, io::Write, sync::Mutex};
+
+use crate::{
+    average, commands::lock_course, format_duration, CommandResult, Outcome, Question, Queue,
+    QueueState, Visit,
+};
+
+/// Longest notes a student can leave, so they fit on the dashboards.
+const MAX_NOTES: usize = 200;
+
+/// Reads a line typed at the terminal, without the newline.
+fn read_line() -> String {
+    let mut line = String::new();
+    if std::io::stdin().read_line(&mut line).is_err() {
+        return String::new();
+    }
+    line.trim().to_owned()
+}
+
+/// Asks at the kiosk what the student needs help with. Both the topic and
+/// the notes can be left blank. The queue is locked only to check the
+/// answers.
+pub fn prompt_question(queue: &Mutex<Queue>, course_id: &str) -> Result<Question, String> {
+    let check = |question| lock_course(queue, course_id, |course| course.check_question(question));
+    let topics = lock_course(queue, course_id, |course| course.config.topics.clone());
+
+    let mut topic = None;
+    if !topics.is_empty() {
+        for (i, topic) in topics.iter().enumerate() {
+            println!("{}: {}", i + 1, topic);
+        }
+        print!("Topic (number or name, blank for none):");
+        std::io::stdout().flush().unwrap();
+        let answer = read_line();
+        topic = match answer.parse::<usize>() {
+            Ok(i) if (1..=topics.len()).contains(&i) => Some(topics[i - 1].clone()),
+            _ => Some(answer),
+        };
+        // Before asking for the notes, so they aren't typed for nothing.
+        topic = check(Question { topic, notes: None })?.topic;
+    }
+
+    print!("What do you need help with? (optional):");
+    std::io::stdout().flush().unwrap();
+    let notes = Some(read_line());
+
+    check(Question { topic, notes })
+}
+
+impl QueueState {
+    /// Checks a question before it is added: the topic has to be one of the
+    /// course's, spelled as configured, and the notes short. Blank fields
+    /// are left out.
+    pub fn check_question(&self, question: Question) -> Result<Question, String> {
+        let topic = match question.topic.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(topic) => match self
+                .config
+                .topics
+                .iter()
+                .find(|known| known.eq_ignore_ascii_case(topic))
+            {
+                Some(known) => Some(known.clone()),
+                None => {
+                    return Err(format!(
+                        "Unknown topic \"{}\", pick one of: {}.",
+                        topic,
+                        self.config.topics.join(", ")
+                    ))
+                }
+            },
+        };
+
+        let notes = question
+            .notes
+            .as_deref()
+            .map(str::trim)
+            .filter(|notes| !notes.is_empty());
+        if notes.is_some_and(|notes| notes.chars().count() > MAX_NOTES) {
+            return Err(format!(
+                "Notes are too long, keep them under {} characters.",
+                MAX_NOTES
+            ));
+        }
+
+        Ok(Question {
+            topic,
+            notes: notes.map(str::to_owned),
+        })
+    }
+
+    /// Prints how much help was asked for on each topic: visits, how they
+    /// ended, average wait and help, and how many are waiting now. The
+    /// course's topics come first, in their order, then any no longer
+    /// configured, then visits without a topic.
+    ///
+    /// `topic_report`
+    pub fn topic_report(&mut self) -> CommandResult {
+        let mut by_topic: BTreeMap<Option<&str>, Vec<&Visit>> = BTreeMap::new();
+        for visit in self.students.values().flat_map(|s| s.queue_times.iter()) {
+            by_topic
+                .entry(visit.question.topic.as_deref())
+                .or_default()
+                .push(visit);
+        }
+        let mut topics = self
+            .config
+            .topics
+            .iter()
+            .map(|topic| Some(topic.as_str()))
+            .collect::<Vec<Option<&str>>>();
+        for topic in by_topic.keys().filter(|topic| topic.is_some()) {
+            if !topics.contains(topic) {
+                topics.push(*topic);
+            }
+        }
+        topics.push(None);
+
+        for topic in topics {
+            let visits = by_topic.remove(&topic).unwrap_or_default();
+            let count = |outcome| {
+                visits
+                    .iter()
+                    .filter(|visit| visit.outcome == outcome)
+                    .count()
+            };
+            let helped = visits
+                .iter()
+                .filter(|visit| visit.was_helped())
+                .collect::<Vec<_>>();
+            let waiting = self
+                .queue
+                .iter()
+                .filter(|queued| queued.question.topic.as_deref() == topic)
+                .count();
+            println!(
+                "{}: {} visits, helped {}, left {}, no-shows {}, average wait {}, \
+                 average help {}, waiting now {}",
+                topic.unwrap_or("No topic"),
+                visits.len(),
+                helped.len(),
+                count(Outcome::Left),
+                count(Outcome::NoShow),
+                average(helped.iter().map(|visit| visit.wait))
+                    .map_or("-".to_owned(), format_duration),
+                average(helped.iter().filter_map(|visit| visit.help))
+                    .map_or("-".to_owned(), format_duration),
+                waiting
+            );
+        }
+        Ok(())
+    }
+}
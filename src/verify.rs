@@ -0,0 +1,291 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+    path::Path,
+    sync::Mutex,
+};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, TimeDelta, Utc};
+use rpassword::read_password;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commands::lock_course,
+    credentials::{hash_secret, verify_secret},
+    persist, CommandResult, Queue, QueueState,
+};
+
+/// How many wrong proofs a student gets within [`LOCKOUT_MINUTES`] before
+/// any proof for them is refused, so a 4 digit one can't be guessed.
+const MAX_FAILED_ATTEMPTS: usize = 5;
+const LOCKOUT_MINUTES: i64 = 15;
+
+/// How students prove who they are when joining a course's queue.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verification {
+    /// Knowing a netid on the roster is enough.
+    #[default]
+    None,
+    /// A PIN handed out when the roster is loaded.
+    Pin,
+    /// The last digits of the student ID number on the roster.
+    StudentId,
+    /// A swipe or scan of the student's ID card, which has to contain their
+    /// student ID number.
+    Card,
+}
+
+/// Student PINs, argon2 hashed and key'd by netid. Like staff passwords,
+/// kept out of the course's state so backups and stats don't carry them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Pins {
+    hashes: BTreeMap<String, String>,
+    #[serde(skip)]
+    path: String,
+}
+impl Pins {
+    /// Reads the PINs at `path`. A missing file has none.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let mut pins = match std::fs::read_to_string(path) {
+            Ok(contents) => match serde_json::from_str::<Pins>(&contents) {
+                Ok(pins) => pins,
+                Err(err) => return Err(format!("Failed to parse PIN file: {}", err)),
+            },
+            Err(_) => Pins::default(),
+        };
+        pins.path = path.to_owned();
+        Ok(pins)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let Ok(output) = serde_json::to_string_pretty(self) else {
+            return Err("Failed to serialize.".to_owned());
+        };
+        if let Err(err) = persist::write_atomic(Path::new(&self.path), output.as_bytes()) {
+            return Err(format!("Failed to write PIN file: {}", err));
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, net_id: &str) -> bool {
+        self.hashes.contains_key(net_id)
+    }
+
+    /// Gives each student a new random 6 digit PIN and returns them. The
+    /// PINs themselves are never stored.
+    pub fn issue(&mut self, net_ids: &[String]) -> Result<Vec<(String, String)>, String> {
+        let mut issued = Vec::new();
+        for net_id in net_ids {
+            let pin = format!("{:06}", OsRng.next_u32() % 1_000_000);
+            self.hashes.insert(net_id.clone(), hash_secret(&pin)?);
+            issued.push((net_id.clone(), pin));
+        }
+        self.save()?;
+        Ok(issued)
+    }
+
+    pub fn verify(&self, net_id: &str, pin: &str) -> bool {
+        match self.hashes.get(net_id) {
+            Some(hash) => verify_secret(hash, pin),
+            None => false,
+        }
+    }
+}
+
+/// Where wrong proofs typed at the terminal come from.
+pub const KIOSK: &str = "kiosk";
+
+/// Recent wrong proofs by netid and by where they came from, the kiosk or
+/// an IP address. Someone guessing is only locked out where they guess
+/// from, so they can't lock a classmate out everywhere. Only kept in
+/// memory, a restart lets everyone try again.
+#[derive(Debug, Clone, Default)]
+pub struct FailedAttempts {
+    by_net_id: HashMap<(String, String), Vec<DateTime<Utc>>>,
+}
+impl FailedAttempts {
+    /// The wrong proofs for `net_id` from `source` within the lockout
+    /// before `now`.
+    fn recent(&mut self, net_id: &str, source: &str, now: DateTime<Utc>) -> usize {
+        let since = now - TimeDelta::minutes(LOCKOUT_MINUTES);
+        let key = (net_id.to_owned(), source.to_owned());
+        let Some(attempts) = self.by_net_id.get_mut(&key) else {
+            return 0;
+        };
+        attempts.retain(|attempt| *attempt > since);
+        let count = attempts.len();
+        if count == 0 {
+            self.by_net_id.remove(&key);
+        }
+        count
+    }
+
+    /// Forgets the wrong proofs for `net_id` from everywhere. Whether there
+    /// were any.
+    fn clear(&mut self, net_id: &str) -> bool {
+        let before = self.by_net_id.len();
+        self.by_net_id.retain(|(locked, _), _| locked != net_id);
+        self.by_net_id.len() < before
+    }
+}
+
+/// Asks whoever is at the kiosk to prove they are `net_id`, if the course
+/// wants that. The queue is locked only to check the proof.
+pub fn verify_at_terminal(queue: &Mutex<Queue>, course_id: &str, net_id: &str) -> CommandResult {
+    let prompt = lock_course(queue, course_id, |course| course.verification_prompt());
+    let proof = prompt.map(|prompt| {
+        print!("{}", prompt);
+        std::io::stdout().flush().unwrap();
+        read_password().unwrap()
+    });
+    lock_course(queue, course_id, |course| {
+        course.verify_student(net_id, proof.as_deref(), KIOSK)
+    })
+}
+
+/// Just the digits of `text`, e.g. of a card swipe with its track markers.
+fn digits(text: &str) -> String {
+    text.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+impl QueueState {
+    /// What the kiosk asks a student for, `None` if nothing.
+    pub fn verification_prompt(&self) -> Option<String> {
+        match self.config.verification {
+            Verification::None => None,
+            Verification::Pin => Some("PIN:".to_owned()),
+            Verification::StudentId => Some(format!(
+                "Last {} digits of your student ID:",
+                self.config.student_id_digits
+            )),
+            Verification::Card => Some("Swipe your ID card:".to_owned()),
+        }
+    }
+
+    /// Checks that whoever joins as `net_id` is them, the way the course is
+    /// configured to. After too many wrong proofs from `source`, refuses any
+    /// from there for a while.
+    pub fn verify_student(
+        &mut self,
+        net_id: &str,
+        proof: Option<&str>,
+        source: &str,
+    ) -> CommandResult {
+        if self.config.verification == Verification::None {
+            return Ok(());
+        }
+        let now = Utc::now();
+        if self.failed_attempts.recent(net_id, source, now) >= MAX_FAILED_ATTEMPTS {
+            return Err(format!(
+                "Too many failed attempts for {}. Try again in {} minutes, or ask course staff \
+                 to run \"clear_lockout {}\".",
+                net_id, LOCKOUT_MINUTES, net_id
+            ));
+        }
+
+        let proof = proof.map(str::trim).unwrap_or_default();
+        let student_id = self
+            .students
+            .get(net_id)
+            .and_then(|student| student.student_id.as_deref())
+            .map(digits)
+            .filter(|student_id| !student_id.is_empty());
+
+        let verified = match self.config.verification {
+            Verification::None => true,
+            Verification::Pin => self.pins.verify(net_id, proof),
+            Verification::StudentId => student_id.is_some_and(|student_id| {
+                let wanted = self.config.student_id_digits.min(student_id.len());
+                !proof.is_empty() && digits(proof) == student_id[student_id.len() - wanted..]
+            }),
+            Verification::Card => {
+                student_id.is_some_and(|student_id| digits(proof).contains(&student_id))
+            }
+        };
+        if !verified {
+            self.failed_attempts
+                .by_net_id
+                .entry((net_id.to_owned(), source.to_owned()))
+                .or_default()
+                .push(now);
+            return Err(format!(
+                "Couldn't verify {}. Contact course staff if you believe this is a mistake.",
+                net_id
+            ));
+        }
+        self.failed_attempts
+            .by_net_id
+            .remove(&(net_id.to_owned(), source.to_owned()));
+        Ok(())
+    }
+
+    /// Lets a student who was locked out after wrong proofs try again right
+    /// away, from everywhere.
+    ///
+    /// `clear_lockout <netid>`
+    pub fn clear_lockout(&mut self, net_id: &str) -> CommandResult {
+        if !self.failed_attempts.clear(net_id) {
+            return Err(format!("{} has no failed attempts.", net_id));
+        }
+        println!("{} can try again.", net_id);
+        Ok(())
+    }
+
+    /// Issues PINs to every student on the roster who doesn't have one yet
+    /// and writes them to a CSV file to hand out.
+    pub fn issue_missing_pins(&mut self) -> CommandResult {
+        let mut net_ids = self
+            .students
+            .iter()
+            .filter(|(net_id, student)| !student.dropped && !self.pins.contains(net_id))
+            .map(|(net_id, _)| net_id.clone())
+            .collect::<Vec<String>>();
+        if net_ids.is_empty() {
+            return Ok(());
+        }
+        net_ids.sort();
+
+        let issued = self.pins.issue(&net_ids)?;
+        let path = format!(
+            "{}.pins-{}.csv",
+            self.config.backup_file,
+            Utc::now().format("%Y%m%dT%H%M%SZ")
+        );
+        let mut csv = String::from("netid,pin\n");
+        for (net_id, pin) in &issued {
+            csv.push_str(&format!("{},{}\n", net_id, pin));
+        }
+        if let Err(err) = std::fs::write(&path, csv) {
+            return Err(format!("Failed to write {}: {}", path, err));
+        }
+
+        println!(
+            "Issued {} PINs, written to {}. Hand them out, then delete the file.",
+            issued.len(),
+            path
+        );
+        Ok(())
+    }
+
+    /// Gives a student a new PIN, e.g. when they forgot theirs.
+    ///
+    /// `issue_pin <netid>`
+    pub fn issue_pin(&mut self, net_id: &str) -> CommandResult {
+        if self.config.verification != Verification::Pin {
+            return Err(format!("{} doesn't use PINs.", self.config.id));
+        }
+        if self
+            .students
+            .get(net_id)
+            .is_none_or(|student| student.dropped)
+        {
+            return Err(format!("{} is not on the roster.", net_id));
+        }
+
+        let issued = self.pins.issue(&[net_id.to_owned()])?;
+        println!("New PIN for {}: {}", net_id, issued[0].1);
+        Ok(())
+    }
+}
@@ -0,0 +1,429 @@
+use std::sync::Mutex;
+
+use chrono::Utc;
+use tiny_http::Method;
+
+use crate::{
+    estimate::format_estimate, format_duration, http::segments, CommandResult, Question, Queue,
+    QueueState,
+};
+
+/// How often the status page reloads itself, in seconds.
+const STATUS_REFRESH: u32 = 10;
+
+/// What the student pages answer with.
+pub enum Page {
+    Html {
+        status: u16,
+        html: String,
+    },
+    /// A 303 to another page, after a form was sent.
+    Redirect(String),
+}
+
+/// Escapes text for HTML.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Reads a field from a form or query string, "a=1&b=2".
+fn form_value(encoded: &str, key: &str) -> Option<String> {
+    encoded.split('&').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        (name == key).then(|| decode(value))
+    })
+}
+
+/// Undoes the percent encoding browsers use for forms.
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => match value
+                .get(i + 1..i + 3)
+                .map(|hex| u8::from_str_radix(hex, 16))
+            {
+                Some(Ok(byte)) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                _ => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn layout(title: &str, refresh: Option<u32>, content: &str) -> String {
+    let refresh = refresh
+        .map(|seconds| format!("<meta http-equiv=\"refresh\" content=\"{}\">", seconds))
+        .unwrap_or_default();
+    format!(
+        "<!DOCTYPE html>\n\
+         <html lang=\"en\">\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         {}\n\
+         <title>{}</title>\n\
+         <style>\n\
+         body {{ font-family: sans-serif; max-width: 32rem; margin: 2rem auto; padding: 0 1rem; }}\n\
+         .error {{ color: #b00020; }}\n\
+         label {{ display: block; margin: 0.5rem 0; }}\n\
+         .big {{ font-size: 2.5rem; font-weight: bold; margin: 0.5rem 0; }}\n\
+         input, button {{ font-size: 1rem; padding: 0.4rem; }}\n\
+         </style>\n\
+         </head>\n\
+         <body>\n\
+         {}\n\
+         </body>\n\
+         </html>\n",
+        refresh,
+        escape(title),
+        content
+    )
+}
+
+fn page(status: u16, title: &str, refresh: Option<u32>, content: &str) -> Page {
+    Page::Html {
+        status,
+        html: layout(title, refresh, content),
+    }
+}
+
+fn not_found() -> Page {
+    page(
+        404,
+        "Not found",
+        None,
+        "<h1>Not found</h1><p><a href=\"/\">All courses</a></p>",
+    )
+}
+
+/// The field for the course's proof, if it asks for one. Typed like a
+/// password so it doesn't show over a shoulder.
+fn proof_field(course: &QueueState) -> String {
+    course
+        .verification_prompt()
+        .map(|prompt| {
+            format!(
+                "<label>{} <input name=\"proof\" type=\"password\" autocomplete=\"off\" \
+                 required></label>\n",
+                escape(prompt.trim_end_matches(':'))
+            )
+        })
+        .unwrap_or_default()
+}
+
+fn join_form(course: &QueueState) -> String {
+    let proof = proof_field(course);
+    let topic = if course.config.topics.is_empty() {
+        String::new()
+    } else {
+        let options = course
+            .config
+            .topics
+            .iter()
+            .map(|topic| format!("<option>{}</option>", escape(topic)))
+            .collect::<String>();
+        format!(
+            "<label>Topic <select name=\"topic\"><option value=\"\">Other</option>{}</select>\
+             </label>\n",
+            options
+        )
+    };
+    format!(
+        "<form method=\"post\" action=\"/courses/{id}/join\">\n\
+         <label>Netid <input name=\"net_id\" autocomplete=\"username\" required></label>\n\
+         {proof}\
+         {topic}\
+         <label>What do you need help with? <input name=\"notes\" maxlength=\"200\"></label>\n\
+         <button>Join the queue</button>\n\
+         </form>\n\
+         <form method=\"get\" action=\"/courses/{id}/status\">\n\
+         <label>Already in line? <input name=\"net_id\" required></label>\n\
+         <button>Find my spot</button>\n\
+         </form>",
+        id = escape(&course.config.id),
+        proof = proof,
+        topic = topic
+    )
+}
+
+/// Serves the student pages, everything outside "/api". `source` is where
+/// the request came from, see [`crate::http::source`].
+pub fn route(queue: &Mutex<Queue>, method: &Method, url: &str, source: &str, body: &str) -> Page {
+    let query = url.split_once('?').map(|(_, query)| query).unwrap_or("");
+    let segments = segments(url);
+
+    let mut queue = queue.lock().unwrap();
+    match (method, segments.as_slice()) {
+        (Method::Get, []) => index(&queue),
+        (_, ["courses", course, rest @ ..]) => {
+            let Some(course_id) = queue.find_course(course).map(str::to_owned) else {
+                return not_found();
+            };
+            let course = queue.courses.get_mut(&course_id).unwrap();
+            match (method, rest) {
+                (Method::Get, []) => course_page(course, None),
+                (Method::Post, ["join"]) => {
+                    let net_id = form_value(body, "net_id").unwrap_or_default();
+                    let net_id = net_id.trim().to_lowercase();
+                    let proof = form_value(body, "proof");
+                    let question = Question {
+                        topic: form_value(body, "topic"),
+                        notes: form_value(body, "notes"),
+                    };
+                    match join(course, &net_id, proof.as_deref(), source, question) {
+                        Ok(()) => Page::Redirect(status_url(course, &net_id)),
+                        Err(err) => course_page(course, Some(&err)),
+                    }
+                }
+                (Method::Get, ["status"]) => {
+                    let net_id = form_value(query, "net_id").unwrap_or_default();
+                    Page::Redirect(status_url(course, net_id.trim()))
+                }
+                (Method::Get, ["status", net_id]) => {
+                    status_page(course, &net_id.to_lowercase(), 200, None)
+                }
+                (Method::Post, ["leave"]) => pressed(
+                    course,
+                    body,
+                    source,
+                    QueueState::leave,
+                    "You left the queue.",
+                ),
+                (Method::Post, ["away"]) => pressed(
+                    course,
+                    body,
+                    source,
+                    QueueState::step_away,
+                    "Your spot is held until you are back.",
+                ),
+                (Method::Post, ["back"]) => {
+                    pressed(course, body, source, QueueState::come_back, "Welcome back.")
+                }
+                (Method::Post, ["here"]) => pressed(
+                    course,
+                    body,
+                    source,
+                    QueueState::confirm,
+                    "Thanks, they know you are there.",
+                ),
+                _ => not_found(),
+            }
+        }
+        _ => not_found(),
+    }
+}
+
+fn status_url(course: &QueueState, net_id: &str) -> String {
+    // Netids are plain, anything else is turned away by the status page.
+    let net_id = net_id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_' || *c == '.')
+        .collect::<String>()
+        .to_lowercase();
+    format!("/courses/{}/status/{}", course.config.id, net_id)
+}
+
+/// `GET /`, every course.
+fn index(queue: &Queue) -> Page {
+    let mut content = String::from("<h1>Office hours</h1>\n<ul>\n");
+    for course in queue.courses.values() {
+        let state = if course.locked {
+            "closed to new students".to_owned()
+        } else {
+            format!("{} waiting", course.queue.len())
+        };
+        content.push_str(&format!(
+            "<li><a href=\"/courses/{}\">{}</a>: {}</li>\n",
+            escape(&course.config.id),
+            escape(course.config.display_name()),
+            state
+        ));
+    }
+    content.push_str("</ul>");
+    page(200, "Office hours", None, &content)
+}
+
+/// `GET /courses/<course>`, with the error from joining if that failed.
+fn course_page(course: &QueueState, error: Option<&str>) -> Page {
+    let mut content = format!("<h1>{}</h1>\n", escape(course.config.display_name()));
+    if let Some(error) = error {
+        content.push_str(&format!("<p class=\"error\">{}</p>\n", escape(error)));
+    }
+
+    if course.locked {
+        content.push_str("<p class=\"big\">The queue is locked.</p>\n");
+        if let Some(reason) = &course.lock_reason {
+            content.push_str(&format!("<p>{}</p>\n", escape(&reason.to_string())));
+        }
+    } else {
+        content.push_str(&format!(
+            "<p class=\"big\">{} waiting</p>\n",
+            course.queue.len()
+        ));
+        let wait = match course.estimated_wait(course.queue.len()) {
+            Some(wait) => format_estimate(wait),
+            None => "unknown, no staff on duty".to_owned(),
+        };
+        content.push_str(&format!(
+            "<p>Estimated wait if you join now: {}</p>\n",
+            wait
+        ));
+    }
+    content.push_str(&join_form(course));
+    content.push_str("\n<p><a href=\"/\">All courses</a></p>");
+
+    let status = if error.is_some() { 400 } else { 200 };
+    page(status, course.config.display_name(), None, &content)
+}
+
+/// `GET /courses/<course>/status/<netid>`, reloads itself every few
+/// seconds.
+fn status_page(course: &QueueState, net_id: &str, status: u16, message: Option<&str>) -> Page {
+    let mut content = format!("<h1>{}</h1>\n", escape(course.config.display_name()));
+    if let Some(message) = message {
+        content.push_str(&format!("<p>{}</p>\n", escape(message)));
+    }
+
+    let now = Utc::now();
+    let ordered = course.ordered();
+    let called = course
+        .position_of(net_id)
+        .and_then(|position| ordered[position].called.as_ref());
+    if let Some(call) = called {
+        content.push_str(&format!(
+            "<p class=\"big\">It's your turn!</p>\n\
+             <p>{} called you. Confirm you are here within {}, or you move back in line.</p>\n\
+             {}",
+            escape(&call.staff),
+            format_duration(course.grace_left(call, now)),
+            button(course, net_id, "here", "I'm here")
+        ));
+    } else if let Some(position) = course.position_of(net_id) {
+        let queued = ordered[position];
+        let (wait, step) = if queued.is_away() {
+            (
+                "none until you are back, your spot is held".to_owned(),
+                button(course, net_id, "back", "I'm back"),
+            )
+        } else {
+            let wait = match course.estimated_wait(position) {
+                Some(wait) => format_estimate(wait),
+                None => "unknown, no staff on duty".to_owned(),
+            };
+            (wait, button(course, net_id, "away", "Step away"))
+        };
+        content.push_str(&format!(
+            "<p>{}, you are</p>\n\
+             <p class=\"big\">#{} in line</p>\n\
+             <p>Waiting for {}. Estimated wait: {}.</p>\n\
+             {}\n\
+             {}",
+            escape(net_id),
+            position + 1,
+            format_duration(queued.time_in_queue(now)),
+            wait,
+            step,
+            button(course, net_id, "leave", "Leave the queue")
+        ));
+    } else if let Some(session) = course
+        .helping
+        .iter()
+        .find(|session| session.student.net_id == net_id)
+    {
+        content.push_str(&format!(
+            "<p class=\"big\">It's your turn!</p>\n<p>{} is helping you.</p>",
+            escape(&session.staff)
+        ));
+    } else {
+        content.push_str(&format!(
+            "<p>{} is not in the queue.</p>\n{}",
+            escape(net_id),
+            join_form(course)
+        ));
+    }
+    content.push_str(&format!(
+        "\n<p><a href=\"/courses/{}\">Back</a></p>",
+        escape(&course.config.id)
+    ));
+
+    page(
+        status,
+        course.config.display_name(),
+        Some(STATUS_REFRESH),
+        &content,
+    )
+}
+
+/// `POST /courses/<course>/join`
+fn join(
+    course: &mut QueueState,
+    net_id: &str,
+    proof: Option<&str>,
+    source: &str,
+    question: Question,
+) -> Result<(), String> {
+    course.check_join(net_id)?;
+    course.verify_student(net_id, proof, source)?;
+    course.add(net_id, question)
+}
+
+/// A button on the status page that posts the student's netid to `action`,
+/// with their proof if the course asks for one. Anyone can open the page.
+fn button(course: &QueueState, net_id: &str, action: &str, label: &str) -> String {
+    format!(
+        "<form method=\"post\" action=\"/courses/{}/{}\">\n\
+         <input type=\"hidden\" name=\"net_id\" value=\"{}\">\n\
+         {}\
+         <button>{}</button>\n\
+         </form>",
+        escape(&course.config.id),
+        action,
+        escape(net_id),
+        proof_field(course),
+        label
+    )
+}
+
+/// One of the status page's buttons was pressed, leave, step away, back or
+/// here: checks the student is in the queue and their proof, then runs
+/// `action` and shows the status page with `message`, or the error.
+fn pressed(
+    course: &mut QueueState,
+    body: &str,
+    source: &str,
+    action: fn(&mut QueueState, &str) -> CommandResult,
+    message: &str,
+) -> Page {
+    let net_id = form_value(body, "net_id").unwrap_or_default();
+    let net_id = net_id.trim().to_lowercase();
+    let proof = form_value(body, "proof");
+    let result = course
+        .ensure_queued(&net_id)
+        .map(|_| ())
+        .and_then(|()| course.verify_student(&net_id, proof.as_deref(), source))
+        .and_then(|()| action(course, &net_id));
+    match result {
+        Ok(()) => status_page(course, &net_id, 200, Some(message)),
+        Err(err) => status_page(course, &net_id, 400, Some(&err)),
+    }
+}